@@ -0,0 +1,92 @@
+use clap::Parser;
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use genetic::genetic::*;
+use genetic::problems::{knapsack::Knapsack, stocks::Market, Problem};
+
+fn market_fitness(c: &mut Criterion) {
+    let mut group = c.benchmark_group("market_fitness");
+    let genotype = b"e010|m002|s101".to_vec();
+
+    for months in [
+        "1_month",
+        "2_months",
+        "4_months",
+        "8_months",
+        "18_months",
+        "36_months",
+        "60_months",
+        "120_months",
+    ] {
+        let market = Market::new(vec![format!("testdata/final-test/AMZN-{months}.txt")]).unwrap();
+
+        group.bench_with_input(BenchmarkId::from_parameter(months), &market, |b, market| {
+            b.iter(|| market.fitness(black_box(&genotype)))
+        });
+    }
+
+    group.finish();
+}
+
+fn knapsack_fitness(c: &mut Criterion) {
+    let mut group = c.benchmark_group("knapsack_fitness");
+
+    for (items, file) in [
+        (25, "test1"),
+        (50, "test50"),
+        (100, "test100"),
+        (200, "test200"),
+        (400, "test400"),
+    ] {
+        let knapsack = Knapsack::new(vec![format!("testdata/knapsack/{file}.kp")]).unwrap();
+        let genotype = knapsack.generate_genotype(false).genotype;
+
+        group.bench_with_input(
+            BenchmarkId::from_parameter(items),
+            &knapsack,
+            |b, knapsack| b.iter(|| knapsack.fitness(black_box(&genotype))),
+        );
+    }
+
+    group.finish();
+}
+
+fn generate_generation(c: &mut Criterion) {
+    let mut group = c.benchmark_group("generate_generation");
+    group.sample_size(20);
+
+    for (name, args) in [
+        (
+            "knapsack",
+            vec![
+                "--problem",
+                "knapsack",
+                "--file",
+                "testdata/knapsack/test100.kp",
+            ],
+        ),
+        (
+            "stocks",
+            vec![
+                "--problem",
+                "stocks",
+                "--file",
+                "testdata/final-test/AMZN-36_months.txt",
+            ],
+        ),
+    ] {
+        let mut generation =
+            Generation::from(Args::parse_from(std::iter::once("genetic").chain(args)));
+
+        group.bench_function(name, |b| b.iter(|| generation.generate_generation(1)));
+    }
+
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    market_fitness,
+    knapsack_fitness,
+    generate_generation
+);
+criterion_main!(benches);
@@ -0,0 +1,154 @@
+use crate::genetic::Args;
+use clap::{parser::ValueSource, ArgAction, ArgMatches, CommandFactory, FromArgMatches};
+use log::{debug, trace};
+use std::{ffi::OsString, fs, path::Path};
+use toml::{Table, Value};
+
+/// Parse the command line, taking any parameters it doesn't set from the
+/// `--config` file. Returns the arguments and the effective configuration.
+pub fn load<I, T>(itr: I) -> (Args, String)
+where
+    I: IntoIterator<Item = T>,
+    T: Into<OsString> + Clone,
+{
+    let argv: Vec<OsString> = itr.into_iter().map(Into::into).collect();
+    let cli = Args::command().get_matches_from(argv.clone());
+
+    let mut full = argv[..1].to_vec();
+
+    if let Some(file) = cli.get_one::<String>("config") {
+        full.extend(from_file(file, &cli).into_iter().map(OsString::from));
+    }
+
+    full.extend(argv.into_iter().skip(1));
+    trace!("Effective arguments: {:?}", full);
+
+    let matches = Args::command().get_matches_from(full);
+    let args = Args::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+
+    (args, to_toml(&matches))
+}
+
+// Convert a configuration file into command line arguments,
+// skipping any that were given on the command line
+fn from_file(file: &str, cli: &ArgMatches) -> Vec<String> {
+    let contents = match fs::read_to_string(file) {
+        Ok(c) => c,
+        Err(e) => {
+            panic!("Error reading {}: {}", file, e);
+        }
+    };
+
+    let table = match Path::new(file).extension().and_then(|e| e.to_str()) {
+        Some("yaml") | Some("yml") => serde_yaml::from_str::<Table>(&contents)
+            .unwrap_or_else(|e| panic!("Failed to parse {file}: {e}")),
+        _ => contents
+            .parse::<Table>()
+            .unwrap_or_else(|e| panic!("Failed to parse {file}: {e}")),
+    };
+
+    let command = Args::command();
+    let mut args = Vec::new();
+
+    for (key, value) in flatten(table) {
+        let id = key.replace('-', "_");
+
+        // configuration files can't include each other
+        if id == "config" {
+            continue;
+        }
+
+        let arg = command
+            .get_arguments()
+            .find(|a| a.get_id() == id.as_str() && a.get_long().is_some())
+            .unwrap_or_else(|| panic!("Unknown option in {file}: {key}"));
+
+        if cli.value_source(&id) == Some(ValueSource::CommandLine) {
+            debug!("Ignoring {key} from {file} in favour of the command line");
+            continue;
+        }
+
+        let flag = format!("--{}", arg.get_long().unwrap());
+
+        match (arg.get_action(), value) {
+            (ArgAction::SetTrue, Value::Boolean(b)) => {
+                if b {
+                    args.push(flag);
+                }
+            }
+            (ArgAction::SetTrue, v) => {
+                panic!("Expected true or false for {key} in {file}, found {v}");
+            }
+            (_, Value::Array(values)) => {
+                if !values.is_empty() {
+                    args.push(flag);
+                    args.extend(values.into_iter().map(to_arg));
+                }
+            }
+            (_, v) => {
+                args.push(flag);
+                args.push(to_arg(v));
+            }
+        }
+    }
+
+    args
+}
+
+// Sections only group options, so [stocks] funds = 1 is the same as funds = 1
+fn flatten(table: Table) -> Vec<(String, Value)> {
+    table
+        .into_iter()
+        .flat_map(|(key, value)| match value {
+            Value::Table(t) => flatten(t),
+            v => vec![(key, v)],
+        })
+        .collect()
+}
+
+fn to_arg(value: Value) -> String {
+    match value {
+        Value::String(s) => s,
+        v => v.to_string(),
+    }
+}
+
+// Render every parameter of the run, so the output records how it was produced
+fn to_toml(matches: &ArgMatches) -> String {
+    let mut table = Table::new();
+
+    for arg in Args::command().get_arguments() {
+        let id = arg.get_id().as_str();
+
+        // rerunning from the output file shouldn't overwrite it
+        if id == "config" || id == "output" || arg.get_long().is_none() {
+            continue;
+        }
+
+        let values: Vec<String> = match matches.get_raw(id) {
+            Some(v) => v.map(|v| v.to_string_lossy().into_owned()).collect(),
+            None => Vec::new(),
+        };
+
+        let value = match arg.get_action() {
+            ArgAction::SetTrue => Value::Boolean(matches.get_flag(id)),
+            ArgAction::Append => Value::Array(values.into_iter().map(Value::String).collect()),
+            _ => match values.into_iter().next() {
+                Some(v) => {
+                    if let Ok(i) = v.parse::<i64>() {
+                        Value::Integer(i)
+                    } else if let Ok(f) = v.parse::<f64>() {
+                        Value::Float(f)
+                    } else {
+                        Value::String(v)
+                    }
+                }
+                None => continue,
+            },
+        };
+
+        table.insert(id.to_string(), value);
+    }
+
+    table.to_string()
+}
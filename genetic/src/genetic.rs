@@ -1,5 +1,7 @@
 use crate::problems::*;
-use clap::{Parser, ValueEnum};
+use crate::synthetic;
+use clap::{Parser, Subcommand, ValueEnum};
+use indicatif::{ProgressBar, ProgressStyle};
 use log::{debug, error, info, trace};
 use rand::{
     distributions::{Distribution, WeightedIndex},
@@ -9,6 +11,7 @@ use rand::{
 use std::{
     fmt::{Display, Formatter, Result},
     panic,
+    sync::mpsc::Receiver,
 };
 
 #[derive(Clone, Debug)]
@@ -18,6 +21,7 @@ pub struct Genotype {
 }
 
 pub struct Generation {
+    pub best: usize,
     pub force_create: bool,
     pub detect_crowding: f64,
     pub max_generations: usize,
@@ -29,12 +33,29 @@ pub struct Generation {
     pub problem: Box<dyn Problem>,
     pub selection_method: SelectionMethod,
     pub sex_method: SexMethod,
+    pub progress: ProgressBar,
+    pub interrupt: Option<Receiver<()>>,
 }
 
+// Invalid is declared first so that it orders below every valid fitness
 #[derive(Clone, Debug, PartialEq, PartialOrd)]
 pub enum Fitness {
-    Valid(f64),
     Invalid,
+    Valid(f64),
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum StopReason {
+    MaxGenerations,
+    Interrupted,
+}
+
+pub struct RunResult {
+    /// The best distinct genotypes seen in any generation, best first
+    pub best: Vec<Genotype>,
+    pub stop_reason: StopReason,
+    /// The fitness of the best genotype in each generation
+    pub history: Vec<Fitness>,
 }
 
 #[derive(Copy, Clone, Debug, ValueEnum)]
@@ -83,6 +104,19 @@ impl Display for SelectionMethod {
     }
 }
 
+impl Display for StopReason {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        match self {
+            StopReason::MaxGenerations => {
+                write!(f, "reached the maximum number of generations")
+            }
+            StopReason::Interrupted => {
+                write!(f, "interrupted")
+            }
+        }
+    }
+}
+
 impl Display for Genotype {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result {
         write!(
@@ -190,6 +224,10 @@ impl Genotype {
         self.genotype.len()
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.genotype.is_empty()
+    }
+
     pub fn new(generation: &Generation) -> Genotype {
         generation
             .problem
@@ -202,7 +240,8 @@ impl Genotype {
 
     pub fn reproduce(&mut self, mate: &mut Genotype, generation: &Generation) {
         let length = self.genotype.len();
-        if length != mate.len() || length != generation.problem.len() {
+        let fixed = !generation.problem.variable_length();
+        if fixed && (length != mate.len() || length != generation.problem.len()) {
             panic!("Genitor lengths are incorrect: {} != {}", self, mate);
         }
 
@@ -213,11 +252,33 @@ impl Genotype {
             return;
         }
 
-        let mut num_points = match generation.sex_method {
+        if fixed {
+            self.point_crossover(mate, generation.sex_method);
+        } else {
+            generation.problem.crossover(self, mate);
+        }
+
+        generation
+            .problem
+            .mutate(generation.mutation_rate, generation.force_mutation, self);
+        generation
+            .problem
+            .mutate(generation.mutation_rate, generation.force_mutation, mate);
+
+        debug!("Produced children: {self}, {mate}");
+    }
+
+    // Swap the segments between alternate crossover points
+    fn point_crossover(&mut self, mate: &mut Genotype, sex_method: SexMethod) {
+        let length = self.genotype.len();
+        let mut rng = rand::thread_rng();
+
+        let mut num_points = match sex_method {
             SexMethod::One => (1..length).choose_multiple(&mut rng, 1),
             SexMethod::Two => (1..length).choose_multiple(&mut rng, 2),
             SexMethod::Uniform => {
-                let num = rng.gen_range(3..length);
+                // at least three points, unless the genotype is too short
+                let num = rng.gen_range(3.min(length.saturating_sub(1))..length.max(1));
                 (1..length).choose_multiple(&mut rng, num)
             }
         };
@@ -238,20 +299,40 @@ impl Genotype {
             toggle = !toggle;
         }
 
-        generation
-            .problem
-            .mutate(generation.mutation_rate, generation.force_mutation, self);
-        generation
-            .problem
-            .mutate(generation.mutation_rate, generation.force_mutation, mate);
-
-        debug!("Produced children: {self}, {mate}");
+        // the segment after the last crossover point
+        if toggle {
+            self.genotype[last..].swap_with_slice(&mut mate.genotype[last..]);
+        }
     }
 }
 
 impl Generation {
     pub fn from(args: Args) -> Generation {
+        let problem: Box<dyn Problem> = match args.problem {
+            ProblemType::Knapsack => {
+                Box::new(knapsack::Knapsack::new(args.file.clone()).expect("Failed to create problem"))
+            }
+            ProblemType::Stocks => Box::new(stocks::Market::with_options(
+                args.file.clone(),
+                args.stocks.clone(),
+            )),
+        };
+
+        Generation::with_problem(args, problem)
+    }
+
+    /// A generation for `problem` instead of the one the arguments describe
+    pub fn with_problem(args: Args, problem: Box<dyn Problem>) -> Generation {
+        let progress = if args.progress {
+            ProgressBar::new(args.max_generations as u64)
+        } else {
+            ProgressBar::hidden()
+        };
+
+        progress.set_style(ProgressStyle::with_template("{msg} {wide_bar} {pos}/{len}").unwrap());
+
         let mut generation = Generation {
+            best: args.best,
             force_create: args.force_create,
             detect_crowding: args.detect_crowding,
             max_generations: args.max_generations,
@@ -260,16 +341,11 @@ impl Generation {
             intermediate: Vec::<Genotype>::with_capacity(args.intermediate_population),
             skip: args.skip,
             mutation_rate: args.mutation_rate,
-            problem: match args.problem {
-                ProblemType::Knapsack => {
-                    Box::new(knapsack::Knapsack::new(args.file).expect("Failed to create problem"))
-                }
-                ProblemType::Stocks => {
-                    Box::new(stocks::Market::new(args.file).expect("Failed to create problem"))
-                }
-            },
+            problem,
             selection_method: args.selection_method,
             sex_method: args.sex_method,
+            progress,
+            interrupt: None,
         };
 
         for g in args.genitors {
@@ -288,7 +364,7 @@ impl Generation {
     }
 
     pub fn best(&self) -> Option<&Genotype> {
-        if self.population.len() > 0 {
+        if !self.population.is_empty() {
             Some(
                 self.population
                     .iter()
@@ -300,6 +376,82 @@ impl Generation {
         }
     }
 
+    /// Evolve the population until the maximum number of generations is
+    /// reached or an interrupt is received on `self.interrupt`
+    pub fn run(&mut self) -> RunResult {
+        assert!(
+            self.best > 0,
+            "Number of best solutions must be greater than 0"
+        );
+
+        let mut result = RunResult {
+            best: Vec::with_capacity(self.best),
+            stop_reason: StopReason::MaxGenerations,
+            history: Vec::with_capacity(self.max_generations),
+        };
+
+        self.record(&mut result);
+
+        for i in 1..self.max_generations {
+            if let Some(rx) = &self.interrupt {
+                if rx.try_recv().is_ok() {
+                    info!("Interrupted after {i} generations");
+                    result.stop_reason = StopReason::Interrupted;
+                    break;
+                }
+            }
+
+            self.generate_generation(i);
+            self.record(&mut result);
+        }
+
+        self.progress.finish();
+
+        result
+    }
+
+    // add the current population to the run's history and best genotypes
+    fn record(&self, result: &mut RunResult) {
+        result.history.push(
+            self.best()
+                .map(|g| g.fitness.clone())
+                .unwrap_or(Fitness::Invalid),
+        );
+
+        for genotype in &self.population {
+            if genotype.fitness == Fitness::Invalid
+                || result.best.iter().any(|b| b.genotype == genotype.genotype)
+            {
+                continue;
+            }
+
+            if result.best.len() == self.best {
+                if genotype.fitness <= result.best[self.best - 1].fitness {
+                    continue;
+                }
+
+                result.best.pop();
+            }
+
+            let index = result
+                .best
+                .partition_point(|b| b.fitness >= genotype.fitness);
+            result.best.insert(index, genotype.clone());
+        }
+
+        if let Some(b) = result.best.first() {
+            info!("Generation: {} Best: {b}", result.history.len() - 1);
+            self.progress
+                .set_message(format!("{:.2}", b.fitness.unwrap()));
+        }
+
+        for i in self.population.iter() {
+            debug!("\t{i}");
+        }
+
+        self.progress.inc(1);
+    }
+
     pub fn select_genitors(&mut self) {
         let mut rng = rand::thread_rng();
 
@@ -308,17 +460,18 @@ impl Generation {
 
         // prepare the pool of genitors
         self.intermediate.clear();
+        let capacity = self.intermediate.capacity();
 
-        if self.population.len() > 0 {
+        if !self.population.is_empty() {
             match self.selection_method {
                 SelectionMethod::Equal => {
-                    let limit = if self.intermediate.len() == 0 {
+                    let limit = if self.intermediate.is_empty() {
                         self.population.len() * 2
                     } else {
                         self.intermediate.len()
                     };
 
-                    for n in 0..self.intermediate.capacity() {
+                    for n in 0..capacity {
                         self.intermediate
                             .push(self.population[rng.gen_range(0..self.population.len())].clone());
 
@@ -355,7 +508,7 @@ impl Generation {
 
                                     // ensure we don't overfill the pool
                                     while f > 0.0 {
-                                        if self.intermediate.len() == self.intermediate.capacity() {
+                                        if self.intermediate.len() == capacity {
                                             break;
                                         } else if f > 1.0 {
                                             debug!("Pushing {genotype} into the pool");
@@ -384,7 +537,7 @@ impl Generation {
 
                         // we don't have a max intermediate population
                         // so just run through the genotypes once
-                        if self.intermediate.len() == self.intermediate.capacity() {
+                        if self.intermediate.len() >= capacity {
                             break;
                         }
                     }
@@ -415,13 +568,13 @@ impl Generation {
                         }
                     };
 
-                    let limit = if self.intermediate.len() < 1 {
+                    let limit = if self.intermediate.is_empty() {
                         self.population.len() * 2
                     } else {
                         self.intermediate.len()
                     };
 
-                    while self.intermediate.len() < limit {
+                    while self.intermediate.len() < limit.min(capacity) {
                         self.intermediate
                             .push(self.population[dist.sample(&mut rng)].clone());
                     }
@@ -442,7 +595,7 @@ impl Generation {
     pub fn generate_genitors(&mut self) {
         debug!("Generating genitors");
         while self.population.len() < self.population.capacity() {
-            self.population.push(Genotype::new(&self));
+            self.population.push(Genotype::new(self));
         }
     }
 
@@ -469,9 +622,12 @@ impl Generation {
     pub fn generate_generation(&mut self, num_generation: usize) {
         let mut rng = rand::thread_rng();
 
-        if !self.population.iter().fold(true, |good, genotype| {
-            good && genotype.len() == self.problem.len()
-        }) {
+        if !self.problem.variable_length()
+            && !self
+                .population
+                .iter()
+                .all(|genotype| genotype.len() == self.problem.len())
+        {
             panic!("Genitor genotype is incorrect length!");
         }
 
@@ -534,7 +690,7 @@ impl Generation {
 }
 
 /// Genetic algorithm to generate optimal solutions
-#[derive(Parser, Debug)]
+#[derive(Parser, Clone, Debug)]
 #[command(author, version, about, long_about)]
 pub struct Args {
     /// Print n best genotypes from all generations
@@ -545,6 +701,10 @@ pub struct Args {
     #[arg(short = 'c', long, default_value_t = false)]
     pub force_create: bool,
 
+    /// Load any parameters not given on the command line from a TOML or YAML file
+    #[arg(long)]
+    pub config: Option<String>,
+
     /// Detect crowding and ramp up mutation rate
     #[arg(short, long, default_value_t = 0.0)]
     pub detect_crowding: f64,
@@ -585,6 +745,11 @@ pub struct Args {
     #[arg(short = 'o', long, default_value_t = false)]
     pub progress: bool,
 
+    /// Also write the configuration and results to a file, which reproduces the run
+    /// when passed back to --config
+    #[arg(long)]
+    pub output: Option<String>,
+
     /// The number of genitors in each population
     #[arg(short, long, default_value_t = 50)]
     pub population: usize,
@@ -604,6 +769,34 @@ pub struct Args {
     /// The method used to produce subsequent generations from genitors
     #[arg(short = 'x', long, value_enum, default_value_t = SexMethod::Uniform)]
     pub sex_method: SexMethod,
+
+    #[command(flatten)]
+    pub stocks: stocks::Options,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// Tools to run instead of the genetic algorithm
+#[derive(Subcommand, Clone, Debug)]
+pub enum Command {
+    /// Write a synthetic price history for testing strategies on
+    Generate(synthetic::Options),
+    /// Describe stock strategies in words, e.g. s020&r714|v715
+    Decode {
+        /// The strategies' genotypes
+        #[arg(required = true)]
+        genotypes: Vec<String>,
+    },
+    /// Paper trade a stock strategy, printing what it does on each bar of a price
+    /// file, or of bars read from standard input as they arrive
+    Paper {
+        /// The strategy's genotype, e.g. s020&r714|v715
+        genotype: String,
+        /// A price file to replay, or standard input if not given, with a closing price
+        /// or a date, closing price and optionally the volume on each line
+        file: Option<String>,
+    },
 }
 
 #[cfg(test)]
@@ -0,0 +1,304 @@
+use std::collections::{HashMap, VecDeque};
+use std::hash::Hash;
+use std::sync::{Arc, RwLock};
+
+/// A moving average that's updated a price at a time, instead of being computed
+/// over its whole window every day. Each has no value until it's been given as
+/// many prices as its days.
+#[derive(Clone, Debug)]
+pub enum Moving {
+    Simple(Sma),
+    Exponential(Ema),
+    Maximum(Extreme),
+    Minimum(Extreme),
+    Weighted(Wma),
+}
+
+/// The mean of the last `days` prices
+#[derive(Clone, Debug)]
+pub struct Sma {
+    days: usize,
+    window: VecDeque<f64>,
+    sum: f64,
+    // prices added to the sum since it was last summed from scratch
+    pushed: usize,
+}
+
+/// The mean of the last `days` prices weighted by 1 - 2 / (days + 1) to the power
+/// of how many days old they are
+#[derive(Clone, Debug)]
+pub struct Ema {
+    days: usize,
+    window: VecDeque<f64>,
+    alpha: f64,
+    // the weight of the oldest price
+    oldest: f64,
+    numerator: f64,
+    denominator: f64,
+    pushed: usize,
+}
+
+/// The mean of the last `days` prices weighted from 1 for the oldest to `days` for
+/// the most recent
+#[derive(Clone, Debug)]
+pub struct Wma {
+    days: usize,
+    window: VecDeque<f64>,
+    sum: f64,
+    numerator: f64,
+    pushed: usize,
+}
+
+/// The highest or lowest of the last `days` prices. Only the prices that can still
+/// become the extreme are kept, in order, so the extreme is always the first.
+#[derive(Clone, Debug)]
+pub struct Extreme {
+    days: usize,
+    highest: bool,
+    // the index and value of every price that nothing newer beats
+    candidates: VecDeque<(usize, f64)>,
+    count: usize,
+}
+
+/// An indicator's value on each day of a history, if it has one then
+pub type Series = Arc<[Option<f64>]>;
+
+/// Indicator series computed the first time they're needed and then shared,
+/// read-only, by every thread evaluating strategies on the same histories
+#[derive(Debug)]
+pub struct Cache<K> {
+    series: RwLock<HashMap<K, Series>>,
+}
+
+impl Moving {
+    pub fn push(&mut self, price: f64) {
+        match self {
+            Moving::Simple(a) => a.push(price),
+            Moving::Exponential(a) => a.push(price),
+            Moving::Maximum(a) | Moving::Minimum(a) => a.push(price),
+            Moving::Weighted(a) => a.push(price),
+        }
+    }
+
+    pub fn value(&self) -> Option<f64> {
+        match self {
+            Moving::Simple(a) => a.value(),
+            Moving::Exponential(a) => a.value(),
+            Moving::Maximum(a) | Moving::Minimum(a) => a.value(),
+            Moving::Weighted(a) => a.value(),
+        }
+    }
+}
+
+impl Sma {
+    pub fn new(days: usize) -> Sma {
+        Sma {
+            days,
+            window: VecDeque::with_capacity(days + 1),
+            sum: 0.0,
+            pushed: 0,
+        }
+    }
+
+    pub fn push(&mut self, price: f64) {
+        if self.days == 0 {
+            return;
+        }
+
+        self.window.push_back(price);
+        self.sum += price;
+
+        if self.window.len() > self.days {
+            self.sum -= self.window.pop_front().unwrap();
+        }
+
+        // sum afresh once every price has been replaced, so rounding errors
+        // don't build up
+        self.pushed += 1;
+
+        if self.pushed == self.days {
+            self.sum = self.window.iter().sum();
+            self.pushed = 0;
+        }
+    }
+
+    pub fn value(&self) -> Option<f64> {
+        (self.days > 0 && self.window.len() == self.days).then(|| self.sum / self.days as f64)
+    }
+}
+
+impl Ema {
+    pub fn new(days: usize) -> Ema {
+        let alpha = 1.0 - 2.0 / (days as f64 + 1.0);
+
+        Ema {
+            days,
+            window: VecDeque::with_capacity(days),
+            alpha,
+            oldest: alpha.powi(days.max(1) as i32 - 1),
+            numerator: 0.0,
+            denominator: (0..days).map(|x| alpha.powi(x as i32)).sum(),
+            pushed: 0,
+        }
+    }
+
+    pub fn push(&mut self, price: f64) {
+        if self.days == 0 {
+            return;
+        }
+
+        if self.window.len() == self.days {
+            self.numerator -= self.window.pop_front().unwrap() * self.oldest;
+        }
+
+        // every price gets a day older
+        self.numerator = self.numerator * self.alpha + price;
+        self.window.push_back(price);
+
+        // sum afresh once every price has been replaced, so rounding errors
+        // don't build up
+        self.pushed += 1;
+
+        if self.pushed == self.days {
+            self.numerator = self
+                .window
+                .iter()
+                .rev()
+                .enumerate()
+                .map(|(age, p)| p * self.alpha.powi(age as i32))
+                .sum();
+            self.pushed = 0;
+        }
+    }
+
+    pub fn value(&self) -> Option<f64> {
+        (self.days > 0 && self.window.len() == self.days)
+            .then(|| self.numerator / self.denominator)
+    }
+}
+
+impl Wma {
+    pub fn new(days: usize) -> Wma {
+        Wma {
+            days,
+            window: VecDeque::with_capacity(days),
+            sum: 0.0,
+            numerator: 0.0,
+            pushed: 0,
+        }
+    }
+
+    pub fn push(&mut self, price: f64) {
+        if self.days == 0 {
+            return;
+        }
+
+        // every price's weight falls by one, and the oldest's to nothing
+        if self.window.len() == self.days {
+            self.numerator -= self.sum;
+            self.sum -= self.window.pop_front().unwrap();
+        }
+
+        self.window.push_back(price);
+        self.numerator += price * self.window.len() as f64;
+        self.sum += price;
+        self.pushed += 1;
+
+        if self.pushed == self.days {
+            self.sum = self.window.iter().sum();
+            self.numerator =
+                self.window.iter().enumerate().map(|(i, p)| p * (i + 1) as f64).sum();
+            self.pushed = 0;
+        }
+    }
+
+    pub fn value(&self) -> Option<f64> {
+        (self.days > 0 && self.window.len() == self.days).then(|| {
+            self.numerator / (self.days * (self.days + 1) / 2) as f64
+        })
+    }
+}
+
+impl Extreme {
+    pub fn max(days: usize) -> Extreme {
+        Extreme::new(days, true)
+    }
+
+    pub fn min(days: usize) -> Extreme {
+        Extreme::new(days, false)
+    }
+
+    fn new(days: usize, highest: bool) -> Extreme {
+        Extreme {
+            days,
+            highest,
+            candidates: VecDeque::new(),
+            count: 0,
+        }
+    }
+
+    pub fn push(&mut self, price: f64) {
+        if self.days == 0 {
+            return;
+        }
+
+        // older prices that this one beats can never be the extreme again
+        while let Some(&(_, last)) = self.candidates.back() {
+            if (self.highest && last <= price) || (!self.highest && last >= price) {
+                self.candidates.pop_back();
+            } else {
+                break;
+            }
+        }
+
+        self.candidates.push_back((self.count, price));
+        self.count += 1;
+
+        while let Some(&(i, _)) = self.candidates.front() {
+            if i + self.days < self.count {
+                self.candidates.pop_front();
+            } else {
+                break;
+            }
+        }
+    }
+
+    pub fn value(&self) -> Option<f64> {
+        if self.days == 0 || self.count < self.days {
+            return None;
+        }
+
+        self.candidates.front().map(|(_, price)| *price)
+    }
+}
+
+impl<K> Default for Cache<K> {
+    fn default() -> Cache<K> {
+        Cache {
+            series: RwLock::new(HashMap::new()),
+        }
+    }
+}
+
+impl<K: Eq + Hash> Cache<K> {
+    /// The series for `key`, computed by `compute` if it hasn't been yet
+    pub fn get(&self, key: K, compute: impl FnOnce() -> Vec<Option<f64>>) -> Series {
+        if let Some(series) = self.series.read().unwrap().get(&key) {
+            return series.clone();
+        }
+
+        // threads missing the same series at once each compute it, but only the
+        // first to finish is kept
+        let series: Series = compute().into();
+        self.series.write().unwrap().entry(key).or_insert(series).clone()
+    }
+
+    /// The number of series computed
+    pub fn len(&self) -> usize {
+        self.series.read().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
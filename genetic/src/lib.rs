@@ -0,0 +1,10 @@
+pub mod config;
+pub mod genetic;
+pub mod indicators;
+pub mod problems;
+mod rules;
+pub mod strategy;
+pub mod synthetic;
+
+#[cfg(test)]
+mod tests;
@@ -1,17 +1,12 @@
-use crate::{genetic::*, problems::*};
-use rayon::prelude::*;
-use clap::Parser;
-use ctrlc;
-use indicatif::ProgressStyle;
-use log::{debug, info, trace, LevelFilter};
+use genetic::problems::stocks::{Bar, History, Market, Paper, TRADING_DAYS};
+use genetic::problems::ProblemType;
+use genetic::{config, genetic::*, strategy::Strategy};
+use log::{info, trace, warn, LevelFilter};
 use simple_logger::SimpleLogger;
-use std::{sync::mpsc::channel, thread, collections::VecDeque};
-
-pub mod genetic;
-pub mod problems;
-
-//#[cfg(test)]
-//mod tests;
+use std::fmt::Display;
+use std::fs::File;
+use std::io::{BufRead, Write};
+use std::sync::mpsc::{channel, Receiver};
 
 fn main() {
     /*
@@ -37,160 +32,286 @@ fn main() {
         }
     }
 
-    let args = Args::parse();
+    let (args, settings) = config::load(std::env::args_os());
 
-    let pb = if !args.progress {
-        indicatif::ProgressBar::hidden()
-    } else {
-        indicatif::ProgressBar::new(300 * 300 * 300) //args.max_generations as u64)
-    };
+    trace!("Arguments: {:?}", args);
 
-    pb.set_style(ProgressStyle::with_template("{msg} {wide_bar} {pos}/{len}").unwrap());
+    match &args.command {
+        Some(Command::Generate(options)) => {
+            options.write();
+            return;
+        }
+        Some(Command::Decode { genotypes }) => {
+            for genotype in genotypes {
+                match Strategy::decode(genotype.as_bytes()) {
+                    Ok(strategy) => println!("{genotype}: {strategy}"),
+                    Err(e) => eprintln!("{genotype} is invalid: {e}"),
+                }
+            }
+            return;
+        }
+        Some(Command::Paper { genotype, file }) => {
+            paper(&args, genotype, file.as_deref());
+            return;
+        }
+        None => {}
+    }
 
-    trace!("Arguments: {:?}", args);
+    println!("Configuration:");
+    println!("{settings}");
 
+    let mut report = Report::new(args.output.as_deref(), &settings);
     let evaluate = args.evaluate;
-    let num = args.best;
+    let stocks = matches!(args.problem, ProblemType::Stocks);
+
+    if stocks && !args.stocks.walk_forward.is_empty() {
+        walk_forward(args, &mut report).unwrap_or_else(|e| fail(e));
+        return;
+    }
+
+    let trades = match args.problem {
+        ProblemType::Stocks => args.stocks.trades.clone().map(|file| (file, args.clone())),
+        ProblemType::Knapsack => None,
+    };
+    let resample = match args.problem {
+        ProblemType::Stocks if args.stocks.bootstrap > 0 => Some(args.clone()),
+        _ => None,
+    };
 
     let mut generation = Generation::from(args);
 
     if evaluate {
-        /*
-        for i in 0..generation.population.len() {
-            println!("{}", generation.problem.format(&generation.population[i]));
+        for g in generation.population.iter() {
+            report.line(generation.problem.format(g));
         }
-        */
-    } else {
-        assert!(num > 0, "Number of best solutions must be greater than 0");
-
-        let mut best = VecDeque::<Genotype>::with_capacity(num);
-        /*
-
-        let guh = "m002|e010|s101".bytes().collect();
-        let fit = generation.problem.fitness(&guh);
-        while best.len() < num {
-            best.push_back(Genotype::from(guh.clone(), fit.clone()));
-        }
-
-        pb.set_message(format!("${:.2}", best.front().unwrap().fitness.unwrap()));
-        let operators = [ b"||" ];
-        let strategies = [ b"ems" ];
-        let mut days = [0, 0, 0];
-
-        let (tx, rx) = channel();
-
-        ctrlc::set_handler(move || tx.send(()).expect("Could not send signal on channel."))
-            .expect("Error setting Ctrl-C handler");
-
-        // brute force search all strategies
-        for op in operators {
-            for strat in strategies.iter() {
-                loop {
-                    let g = format!(
-                        "{}{:03}{}{}{:03}{}{}{:03}",
-                        strat[0] as char,
-                        days[0],
-                        op[0] as char,
-                        strat[1] as char,
-                        days[1],
-                        op[1] as char,
-                        strat[2] as char,
-                        days[2]
-                    )
-                    .bytes()
-                    .collect();
-                    let fit = generation.problem.fitness(&g);
-
-                    let g = Genotype::from(g, fit);
-
-                    if g.fitness > best.front().unwrap().fitness {
-                        best.pop_front();
-                        best.push_back(g.clone());
-                        pb.set_message(format!("${:.2}", best.back().unwrap().fitness.unwrap()));
-                    }
-
-                    if days[0] < 300 {
-                        days[0] += 1;
-                    } else if days[1] < 300 {
-                        days[0] = 0;
-                        days[1] += 1;
-                    } else if days[2] < 300 {
-                        days[0] = 0;
-                        days[1] = 0;
-                        days[2] += 1;
-                    } else {
-                        days[0] = 0;
-                        days[1] = 0;
-                        days[2] = 0;
-                        break;
-                    }
-
-                    pb.inc(1);
-
-                    if rx.try_recv().is_ok() {
-                        println!();
-
-                        for i in best.iter().rev() {
-                            println!("{}", generation.problem.format(&i));
-                        }
-                    }
-                }
-            }
+
+        // a genotype the market can't trade is a mistake in the arguments, which
+        // format has explained
+        if stocks && generation.population.iter().any(|g| g.fitness == Fitness::Invalid) {
+            std::process::exit(1);
+        }
+
+        if let Some(args) = resample {
+            let genotypes: Vec<&[u8]> =
+                generation.population.iter().map(|g| g.genotype.as_slice()).collect();
+            bootstrap(args, &genotypes, &mut report).unwrap_or_else(|e| fail(e));
+        }
+
+        if let (Some((file, args)), Some(best)) = (trades, generation.best()) {
+            write_trades(&file, args, &best.genotype).unwrap_or_else(|e| fail(e));
         }
+    } else {
+        generation.interrupt = Some(interrupt());
+
+        let result = generation.run();
+
+        info!("Stopped: {}", result.stop_reason);
 
-        println!("Best Solution(s): ");
+        report.line("Best Solution(s): ");
 
-        for i in best.iter().rev() {
-            println!("{}", generation.problem.format(&i));
+        for i in result.best.iter() {
+            report.line(generation.problem.format(i));
         }
-        */
-        info!("Generation: 0 Best: {}", best.last().unwrap());
 
-        pb.set_message(format!("${:.2}", best[0].fitness.unwrap()));
+        if let Some(args) = resample {
+            let genotypes: Vec<&[u8]> = result.best.iter().map(|g| g.genotype.as_slice()).collect();
+            bootstrap(args, &genotypes, &mut report).unwrap_or_else(|e| fail(e));
+        }
 
-        for i in generation.population.iter() {
-            debug!("\t{i}");
+        if let (Some((file, args)), Some(best)) = (trades, result.best.first()) {
+            write_trades(&file, args, &best.genotype).unwrap_or_else(|e| fail(e));
         }
+    }
+}
 
-        for i in 1..generation.max_generations {
-            generation.generate_generation(i);
+// Explain why the run can't go on, and stop it with a failing status
+fn fail(error: String) -> ! {
+    eprintln!("{error}");
+    std::process::exit(1);
+}
 
-            pb.inc(1);
+// Paper trade a strategy on every bar of a file, or on bars from standard input
+// until it closes. Bars from standard input are taken to be days.
+fn paper(args: &Args, genotype: &str, file: Option<&str>) {
+    // bars from standard input only have a close
+    if file.is_none() && args.stocks.trade_at_open {
+        fail("Trading at the open needs a price file with opens, not standard input".to_string());
+    }
+
+    let history = file.map(|file| History::read(file, &args.stocks));
+    let (ticker, year) = match &history {
+        Some(history) => (history.ticker.as_str(), history.year()),
+        None => ("stdin", TRADING_DAYS),
+    };
 
-            let new = &generation.population[0];
+    let mut paper = match Paper::new(args.stocks.clone(), ticker, genotype.as_bytes(), year) {
+        Ok(paper) => paper,
+        Err(e) => fail(e),
+    };
 
-            //pb.println(format!("{}", generation.problem.format(&new)));
+    if let Ok(strategy) = Strategy::decode(genotype.as_bytes()) {
+        println!("Paper trading {ticker}: {strategy}");
+    }
 
-            if new.fitness > best.first().unwrap().fitness {
-                if best.len() == num {
-                    best.remove(0);
+    match history {
+        Some(history) => {
+            for day in 0..history.len() {
+                match paper.push(history.bar(day), day + 1 == history.len()) {
+                    Ok(tick) => println!("{tick}"),
+                    Err(e) => fail(e),
+                }
+            }
+        }
+        None => {
+            for line in std::io::stdin().lock().lines().map_while(Result::ok) {
+                if line.trim().is_empty() {
+                    continue;
                 }
 
-                best.push(new.clone());
-                pb.set_message(format!("${:.2}", best.last().unwrap().fitness.unwrap()));
+                match line.parse::<Bar>() {
+                    Ok(bar) => match paper.push(bar, false) {
+                        Ok(tick) => println!("{tick}"),
+                        Err(e) => fail(e),
+                    },
+                    Err(e) => warn!("{e}"),
+                }
             }
+        }
+    }
+
+    println!("Made ${:.2} over {} trades", paper.profit(), paper.trades().len());
+}
+
+// The problem is only known to be a market here, so build it again
+fn write_trades(file: &str, args: Args, genotype: &[u8]) -> Result<(), String> {
+    let market = Market::with_options(args.file, args.stocks);
+    let samples: Vec<(&str, &Market, &[u8])> = market
+        .samples()
+        .into_iter()
+        .map(|(name, m)| (name, m, genotype))
+        .collect();
+
+    Market::write_trades(file, &samples)
+}
+
+// Resample what each strategy makes on each sample, to see how much of it is luck
+fn bootstrap(args: Args, genotypes: &[&[u8]], report: &mut Report) -> Result<(), String> {
+    let market = Market::with_options(args.file, args.stocks.clone());
+
+    report.line("Bootstrap:");
 
-            info!("Generation: {i} Best: {}", best.last().unwrap());
+    for genotype in genotypes {
+        for (name, m) in market.samples() {
+            let results = m.bootstrap(genotype, args.stocks.bootstrap, rand::random())?;
 
-            for i in generation.population.iter() {
-                debug!("\t{i}");
+            report.line(format!(
+                "{} {name}: {}",
+                String::from_utf8_lossy(genotype),
+                results.describe(args.stocks.confidence)
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+// The run's results, printed and, with --output, written to a file after the
+// configuration. Results are TOML comments there, so the file reproduces the run
+// when passed back to --config.
+struct Report {
+    file: Option<(String, File)>,
+}
+
+impl Report {
+    fn new(output: Option<&str>, settings: &str) -> Report {
+        let file = output.map(|output| match File::create(output) {
+            Ok(mut file) => {
+                if let Err(e) = write!(file, "{settings}") {
+                    panic!("Error writing {output}: {e}");
+                }
+
+                (output.to_string(), file)
             }
+            Err(e) => panic!("Error writing {output}: {e}"),
+        });
 
-            if rx.try_recv().is_ok() {
-                println!();
+        Report { file }
+    }
 
-                for i in best.iter().rev() {
-                    println!("{}", generation.problem.format(&i));
+    fn line(&mut self, line: impl Display) {
+        println!("{line}");
+
+        if let Some((output, file)) = &mut self.file {
+            for l in line.to_string().lines() {
+                if let Err(e) = writeln!(file, "# {l}") {
+                    panic!("Error writing {output}: {e}");
                 }
             }
         }
+    }
+}
+
+fn interrupt() -> Receiver<()> {
+    let (tx, rx) = channel();
+
+    ctrlc::set_handler(move || tx.send(()).expect("Could not send signal on channel."))
+        .expect("Error setting Ctrl-C handler");
+
+    rx
+}
+
+// Evolve on each window and test the best strategy on the days after it
+fn walk_forward(args: Args, report: &mut Report) -> Result<(), String> {
+    let market = Market::with_options(args.file.clone(), args.stocks.clone());
+    let windows = market.windows();
+    let mut rx = Some(interrupt());
+    let mut total = 0.0;
+    let mut tested = Vec::new();
+
+    if windows.is_empty() {
+        report.line("The histories are too short for any walk-forward windows");
+        return Ok(());
+    }
+
+    for (n, (train, test)) in windows.into_iter().enumerate() {
+        let mut generation = Generation::with_problem(args.clone(), Box::new(train));
+        generation.interrupt = rx.take();
 
-        pb.finish();
+        let result = generation.run();
+        rx = generation.interrupt.take();
+        info!("Stopped window {}: {}", n + 1, result.stop_reason);
 
-        println!("Best Solution(s): ");
+        // no strategy could be traded on the window, so there's nothing to test
+        if let Some(best) = result.best.first() {
+            total += test.returns(&best.genotype)?.iter().sum::<f64>();
 
-        for i in best.iter().rev() {
-            println!("{}", generation.problem.format(&i));
+            report.line(format!(
+                "Window {}: {} in sample | {} out of sample",
+                n + 1,
+                generation.problem.format(best),
+                test.summary(&best.genotype)?
+            ));
+
+            tested.push((format!("window {}", n + 1), test, best.genotype.clone()));
+        } else {
+            report.line(format!("Window {}: skipped, no valid strategy in sample", n + 1));
+        }
+
+        if result.stop_reason == StopReason::Interrupted {
+            break;
         }
     }
+
+    report.line(format!("Out of sample: ${total:.2}"));
+
+    if let Some(file) = &args.stocks.trades {
+        let samples: Vec<(&str, &Market, &[u8])> = tested
+            .iter()
+            .map(|(name, m, g)| (name.as_str(), m, g.as_slice()))
+            .collect();
+
+        Market::write_trades(file, &samples)?;
+    }
+
+    Ok(())
 }
@@ -4,12 +4,26 @@ use rand::Rng;
 use std::fmt::{Display, Formatter, Result};
 
 pub trait Problem {
-    fn fitness(&self, genotype: &Vec<u8>) -> Fitness;
+    fn fitness(&self, genotype: &[u8]) -> Fitness;
     fn mutate(&self, mutation_rate: f64, force_mutation: bool, genotype: &mut Genotype);
     fn generate_genotype(&self, force_create: bool) -> Genotype;
     fn format(&self, g: &Genotype) -> String;
-    fn alphabet(&self) -> &Vec<u8>;
+    fn alphabet(&self) -> &[u8];
+    /// The length of every genotype, or 0 if their lengths vary
     fn len(&self) -> usize;
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    /// Whether genotypes vary in length, so they're bred with `crossover` instead
+    /// of point crossover. Problems that say so must override `crossover` too.
+    fn variable_length(&self) -> bool {
+        false
+    }
+    /// Swaps material between two genotypes whose lengths vary, which point
+    /// crossover can't do
+    fn crossover(&self, _a: &mut Genotype, _b: &mut Genotype) {
+        panic!("Genotypes of varying length need their own crossover");
+    }
     fn new(files: Vec<String>) -> Option<Self>
     where
         Self: Sized;
@@ -49,7 +63,7 @@ pub mod knapsack {
     }
 
     impl Problem for Knapsack {
-        fn fitness(&self, g: &Vec<u8>) -> Fitness {
+        fn fitness(&self, g: &[u8]) -> Fitness {
             let (weight, value) = self
                 .items
                 .iter()
@@ -99,7 +113,7 @@ pub mod knapsack {
 
             while g.len() < g.genotype.capacity() {
                 g.genotype
-                    .push(self.alphabet()[rng.gen_range(0..self.len())]);
+                    .push(self.alphabet()[rng.gen_range(0..self.alphabet().len())]);
             }
 
             trace!(
@@ -121,7 +135,7 @@ pub mod knapsack {
             g
         }
 
-        fn alphabet(&self) -> &Vec<u8> {
+        fn alphabet(&self) -> &[u8] {
             &self.alphabet
         }
 
@@ -137,7 +151,8 @@ pub mod knapsack {
             for (n, c) in g.genotype.iter_mut().enumerate() {
                 if rng.gen_bool(mutation_rate) {
                     trace!("Mutated gene {n} from: {}", *c as char);
-                    if force_mutation {
+                    // a single allele can't be mutated into anything else
+                    if force_mutation && self.alphabet().len() > 1 {
                         let n = rng.gen_range(0..self.alphabet().len() - 1);
                         let m = self.alphabet()[n];
                         *c = if m == *c { self.alphabet()[n + 1] } else { m };
@@ -235,341 +250,2999 @@ pub mod knapsack {
 }
 
 pub mod stocks {
-    use log::{debug, trace};
+    use chrono::{Datelike, Duration, Months, NaiveDate, NaiveDateTime, NaiveTime, Timelike};
+    use clap::ValueEnum;
+    use log::{debug, trace, warn};
     use rayon::prelude::*;
-    use rand::{thread_rng, Rng};
+    use rand::{rngs::StdRng, seq::{IteratorRandom, SliceRandom}, thread_rng, Rng, SeedableRng};
 
     use crate::genetic::{Fitness, Genotype};
-    use crate::Problem;
+    use crate::problems::Problem;
+    use crate::indicators::{Cache, Ema, Extreme, Moving, Series, Sma, Wma};
+    use crate::rules::Rule;
+    use crate::strategy::{Condition, Strategy};
+    use std::fmt::{Display, Formatter};
     use std::fs::File;
     use std::io::{BufRead, BufReader};
+    use std::path::Path;
+    use std::sync::OnceLock;
+
+    // Longer than a weekend plus a holiday
+    const MAX_GAP: i64 = 4;
+
+    // Days of returns the volatility of a stock is measured over
+    const VOLATILITY_DAYS: usize = 20;
+
+    // Direction genes: long, short or both
+    const DIRECTIONS: [u8; 3] = [b'l', b's', b'b'];
+
+    const DATE_FORMATS: [&str; 3] = ["%Y-%m-%d", "%m/%d/%Y", "%Y%m%d"];
+
+    const TIME_FORMATS: [&str; 5] = [
+        "%Y-%m-%d %H:%M:%S",
+        "%Y-%m-%dT%H:%M:%S",
+        "%Y-%m-%d %H:%M",
+        "%Y-%m-%dT%H:%M",
+        "%m/%d/%Y %H:%M",
+    ];
+
+    /// Options for the stocks problem
+    #[derive(clap::Args, Clone, Debug, Default)]
+    #[command(next_help_heading = "Stocks")]
+    pub struct Options {
+        /// Read a CSV field from a differently named column, e.g. close="Adj Close"
+        #[arg(long, num_args = 1.., value_name = "FIELD=HEADER")]
+        pub columns: Vec<String>,
+
+        /// Trade at the next day's open instead of the current close
+        #[arg(long, default_value_t = false)]
+        pub trade_at_open: bool,
+
+        /// The order of prices in plain-text files, assumed oldest first if not given
+        #[arg(long, value_enum)]
+        pub order: Option<Order>,
+
+        /// Combine the bars of CSV histories into longer ones, e.g. 15m, 1h, 1d or 1w
+        #[arg(long, value_name = "FREQUENCY")]
+        pub resample: Option<Frequency>,
+
+        /// Dollars charged for every trade
+        #[arg(long, default_value_t = 0.0)]
+        pub commission: f64,
+
+        /// Fraction of the value of every trade charged as a fee, e.g. 0.001 for 0.1%
+        #[arg(long, default_value_t = 0.0)]
+        pub fee: f64,
+
+        /// Fraction the price moves against every trade before it fills
+        #[arg(long, default_value_t = 0.0)]
+        pub slippage: f64,
+
+        /// Buy fractional shares instead of only whole ones
+        #[arg(long, default_value_t = false)]
+        pub fractional: bool,
+
+        /// What the fitness of a strategy measures
+        #[arg(long, value_enum, default_value_t = Objective::TotalReturn)]
+        pub objective: Objective,
+
+        /// Combine the objective on each ticker into the fitness this way, instead of
+        /// totalling returns and averaging ratios
+        #[arg(long, value_enum, conflicts_with = "portfolio")]
+        pub aggregate: Option<Aggregate>,
+
+        /// Train on the start of each history and hold out the rest for testing, split
+        /// where validation and then testing begin, as fractions (0.6 0.8) or dates
+        #[arg(long, num_args = 1..=2, value_name = "FRACTION|DATE")]
+        pub split: Vec<String>,
+
+        /// Evolve on windows of TRAIN days and test on the TEST days after each,
+        /// rolling forward TEST days at a time
+        #[arg(long, num_args = 2, value_names = ["TRAIN", "TEST"], conflicts_with = "split")]
+        pub walk_forward: Vec<usize>,
+
+        /// Test the best strategies on these histories without training on them
+        #[arg(long, num_args = 1..)]
+        pub test_file: Vec<String>,
+
+        /// Write every trade of the best strategy to FILE as CSV, and its daily equity
+        /// to the same name ending in -equity
+        #[arg(long, value_name = "FILE")]
+        pub trades: Option<String>,
+
+        /// Resample the best strategies' results this many times, to report how much
+        /// of them could be luck
+        #[arg(long, default_value_t = 0, value_name = "SAMPLES")]
+        pub bootstrap: usize,
+
+        /// How the bootstrap resamples results
+        #[arg(long, value_enum, default_value_t = Resampling::Blocks)]
+        pub resampling: Resampling,
+
+        /// Days of returns in each block the bootstrap draws
+        #[arg(long, default_value_t = 20)]
+        pub block: usize,
+
+        /// The chance each bootstrap confidence interval covers the result
+        #[arg(long, default_value_t = 0.95)]
+        pub confidence: f64,
+
+        /// Trade every stock from one account on a shared calendar, instead of each
+        /// with its own
+        #[arg(long, default_value_t = false)]
+        pub portfolio: bool,
+
+        /// How much of the portfolio to put into each position
+        #[arg(long, value_enum, default_value_t = Sizing::Equal)]
+        pub sizing: Sizing,
+
+        /// The fraction of the portfolio each position takes with fraction sizing
+        #[arg(long, default_value_t = 0.1, value_parser = fraction)]
+        pub fraction: f64,
+
+        /// Evolve a direction gene, which trades long, short or both ways by selling
+        /// short when the strategy sells
+        #[arg(long, default_value_t = false, conflicts_with_all = ["rules", "portfolio"])]
+        pub short: bool,
+
+        /// The most a position can be worth as a multiple of the capital behind it,
+        /// 1 if not given
+        #[arg(long, conflicts_with = "portfolio")]
+        pub leverage: Option<f64>,
+
+        /// Yearly interest charged on the value of shares sold short and on cash
+        /// borrowed, e.g. 0.05 for 5%
+        #[arg(long, default_value_t = 0.0)]
+        pub borrow_rate: f64,
+
+        /// Close a position when equity falls below this fraction of its value
+        #[arg(long, default_value_t = 0.25)]
+        pub margin: f64,
+
+        /// Evolve separate exit genes, which sell when they hold, and stop-loss,
+        /// trailing-stop, take-profit and minimum holding period genes
+        #[arg(long, default_value_t = false, conflicts_with = "rules")]
+        pub exits: bool,
+
+        /// Evolve strategies as rule trees, e.g. "(close > sma(50) & rsi(14) < 70)",
+        /// instead of three genes
+        #[arg(long, default_value_t = false)]
+        pub rules: bool,
+
+        /// The deepest a rule tree can grow
+        #[arg(long, default_value_t = 4)]
+        pub max_depth: usize,
+
+        /// Subtracted from the fitness of a rule tree for each of its rules and values
+        #[arg(long, default_value_t = 0.0)]
+        pub parsimony: f64,
+    }
 
-    #[derive(Clone, Copy)]
-    enum Average {
+    /// The fitness of a trading strategy. Ratios are averaged across stocks and are 0
+    /// when they are undefined, e.g. the Sharpe ratio of a strategy that never trades.
+    #[derive(Clone, Copy, Debug, Default, PartialEq, ValueEnum)]
+    pub enum Objective {
+        /// Dollars made across every stock
+        #[default]
+        TotalReturn,
+        /// Compound annual growth rate
+        Cagr,
+        /// Annualized mean over standard deviation of daily returns
+        Sharpe,
+        /// Annualized mean over downside deviation of daily returns
+        Sortino,
+        /// CAGR over maximum drawdown
+        Calmar,
+        /// The largest fall from a peak, negated so smaller drawdowns are fitter
+        MaxDrawdown,
+        /// Dollars made across every stock over the number of trades
+        ProfitPerTrade,
+    }
+
+    /// How the objective on each ticker is combined into a strategy's fitness, so
+    /// that no one ticker decides it
+    #[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+    pub enum Aggregate {
+        /// The mean across tickers
+        Mean,
+        /// The median across tickers
+        Median,
+        /// The worst ticker's
+        Worst,
+        /// The fraction of tickers on which it beats buying and holding
+        BeatHold,
+    }
+
+    /// How the bootstrap draws samples of a strategy's results
+    #[derive(Clone, Copy, Debug, Default, PartialEq, ValueEnum)]
+    pub enum Resampling {
+        /// Blocks of consecutive daily returns drawn with replacement, keeping the
+        /// days buying and holding returned alongside
+        #[default]
+        Blocks,
+        /// Trading from a random day in the first half of each history
+        Starts,
+    }
+
+    /// How positions are sized in portfolio mode
+    #[derive(Clone, Copy, Debug, Default, PartialEq, ValueEnum)]
+    pub enum Sizing {
+        /// The portfolio split equally between every stock
+        #[default]
+        Equal,
+        /// Shares of the portfolio inversely proportional to each stock's volatility
+        Volatility,
+        /// A fixed fraction of the portfolio
+        Fraction,
+    }
+
+    /// How long a bar is, to resample histories to
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    pub enum Frequency {
+        Minutes(u32),
+        Hours(u32),
+        Days(u32),
+        Weeks(u32),
+    }
+
+    /// The order prices are listed in
+    #[derive(Clone, Copy, Debug, Default, PartialEq, ValueEnum)]
+    pub enum Order {
+        #[default]
+        OldestFirst,
+        NewestFirst,
+    }
+
+    /// The result of trading a single stock
+    #[derive(Clone, Debug)]
+    pub struct Simulation {
+        pub profit: f64,
+        pub trades: usize,
+        /// Capital, gains and the value of shares held at each day's close
+        pub equity: Vec<f64>,
+        /// Every trade, if they were recorded
+        pub ledger: Vec<Trade>,
+        /// Bars in a year, to annualize returns over
+        pub year: f64,
+    }
+
+    /// What a strategy made on each bootstrap sample
+    #[derive(Clone, Debug, Default)]
+    pub struct Bootstrap {
+        /// Dollars made across every stock
+        pub returns: Vec<f64>,
+        /// Maximum drawdowns, averaged across stocks
+        pub drawdowns: Vec<f64>,
+        /// Whether it made more than buying and holding
+        pub beaten: Vec<bool>,
+    }
+
+    /// A single buy or sell, and the capital and gains left after it
+    #[derive(Clone, Debug, PartialEq)]
+    pub struct Trade {
+        pub day: usize,
+        pub buy: bool,
+        pub price: f64,
+        pub shares: f64,
+        pub capital: f64,
+        pub gains: f64,
+    }
+
+    /// A bar arriving while paper trading. Bars read from standard input only have
+    /// a close, and optionally a time and volume.
+    #[derive(Clone, Copy, Debug, Default, PartialEq)]
+    pub struct Bar {
+        pub time: Option<NaiveDateTime>,
+        pub open: Option<f64>,
+        pub close: f64,
+        pub volume: Option<f64>,
+    }
+
+    /// Trades a strategy on one stock a bar at a time, as the bars arrive, reading
+    /// its genes and trading its account as a backtest would. Given every bar of a
+    /// history, with the last one marked, it makes exactly the backtest's trades.
+    pub struct Paper {
+        market: Market,
+        entry: Genes,
+        exit: Option<Genes>,
+        // the entry genes then any exit genes, as written in the genotype
+        genes: Vec<(String, Reader)>,
+        plan: Plan,
+        actor: Actor,
+        interest: f64,
+        closed: bool,
+    }
+
+    /// What paper trading a strategy did on a bar
+    #[derive(Clone, Debug)]
+    pub struct Tick {
+        pub day: usize,
+        pub time: Option<NaiveDateTime>,
+        pub close: f64,
+        pub signal: Signal,
+        /// Each gene, with its value and whether it was bullish
+        pub genes: Vec<(String, Option<f64>, bool)>,
+        /// The trades made on the bar
+        pub trades: Vec<Trade>,
+        /// Capital, gains and the value of the shares held at the bar's close
+        pub equity: f64,
+    }
+
+    /// The prices of a single stock, a bar at a time and oldest first. Plain-text
+    /// histories only have closing prices, so the other fields are empty.
+    #[derive(Clone, Debug, Default)]
+    pub struct History {
+        pub ticker: String,
+        pub name: String,
+        pub range: Option<(NaiveDate, NaiveDate)>,
+        /// The order the prices were listed in the file
+        pub order: Order,
+        /// The first day to trade, earlier days are only used by indicators
+        pub start: usize,
+        /// When each bar starts, at midnight for daily bars
+        pub times: Vec<NaiveDateTime>,
+        pub open: Vec<f64>,
+        pub high: Vec<f64>,
+        pub low: Vec<f64>,
+        pub close: Vec<f64>,
+        pub volume: Vec<f64>,
+    }
+
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    pub enum Average {
         Simple(usize),
         Exponential(usize),
         Maximum(usize),
+        Minimum(usize),
+        Weighted(usize),
+    }
+
+    /// What a gene compares. Averages are bullish below the price, the others
+    /// above their threshold.
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    pub enum Indicator {
+        Average(Average),
+        /// Relative strength index over `days`
+        Rsi { days: usize, level: f64 },
+        /// Difference between the MACD line and its signal line, where the slow
+        /// EMA is over `days`, the fast one over half as many and the signal over a third
+        Macd(usize),
+        /// Standard deviations from the mean over `days`
+        Bollinger { days: usize, deviations: f64 },
+        /// Percentage change from `days` ago
+        RateOfChange { days: usize, percent: f64 },
+        /// The day's volume as a multiple of its average over `days`
+        Volume { days: usize, ratio: f64 },
+    }
+
+    /// What a gene says about a day, and the value it said it with
+    #[derive(Clone, Copy)]
+    pub(crate) struct Reading {
+        pub(crate) bullish: bool,
+        pub(crate) value: Option<f64>,
+    }
+
+    /// Reads a gene's indicator day by day, updating it from the day before
+    pub(crate) struct Reader {
+        indicator: Indicator,
+        // a moving average and how many prices it's been given
+        average: Option<(Moving, usize)>,
+        macd: Option<(usize, f64, f64, f64)>,
     }
 
     pub struct Market {
         pub alphabet: Vec<u8>,
-        pub histories: Vec<Vec<f64>>,
+        pub histories: Vec<History>,
         pub funds: f64,
+        pub options: Options,
+        /// Named histories to test on that aren't trained on
+        pub held_out: Vec<(String, Market)>,
+        /// The objective on each ticker when buying and holding, once it's needed
+        pub held: OnceLock<Vec<f64>>,
+        /// The series of each indicator on each history, by gene letter, days and
+        /// history, as they're needed
+        pub cache: Cache<(u8, usize, usize)>,
     }
 
     struct Actor {
         capital: f64,
         gains: f64,
-        stocks: usize,
-        strategy: (Average, char, Average, char, Average),
+        stocks: f64,
+        trades: usize,
+        ledger: Option<Vec<Trade>>,
+        /// The average price paid for the shares held, or they were sold short at
+        entry: f64,
+        /// The best price since the position was opened, i.e. the highest while
+        /// long and the lowest while short
+        peak: f64,
+        /// The day the position was opened
+        bought: usize,
+    }
+
+    impl Actor {
+        fn new(capital: f64, record: bool) -> Actor {
+            Actor {
+                capital,
+                gains: 0.0,
+                stocks: 0.0,
+                trades: 0,
+                ledger: record.then(Vec::new),
+                entry: 0.0,
+                peak: 0.0,
+                bought: 0,
+            }
+        }
+
+        fn record(&mut self, day: usize, buy: bool, price: f64, shares: f64) {
+            if let Some(ledger) = &mut self.ledger {
+                ledger.push(Trade {
+                    day,
+                    buy,
+                    price,
+                    shares,
+                    capital: self.capital,
+                    gains: self.gains,
+                });
+            }
+        }
+    }
+
+    /// What a strategy says to do on a day
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    pub enum Signal {
+        Buy,
+        Sell,
+        Hold,
+    }
+
+    /// Which positions buy and sell signals open. Long positions are closed by a
+    /// sell and short ones by a buy.
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    pub enum Direction {
+        Long,
+        Short,
+        Both,
+    }
+
+    // What a strategy does on each day of a history, from the first day it trades
+    struct Plan {
+        first: usize,
+        stops: Stops,
+        direction: Direction,
+        signals: Vec<Signal>,
+    }
+
+    // What each of three genes says about a day
+    type Readings = (Reading, Reading, Reading);
+
+    // The days of each history that fall on a day of the calendar
+    pub type Bars = Vec<Vec<(usize, usize)>>;
+
+    // The result of carrying out plans: a simulation of each stock, and of the
+    // account trading all of them in portfolio mode
+    struct Outcome {
+        stocks: Vec<Simulation>,
+        account: Option<Simulation>,
+    }
+
+    /// How a position is managed once it's open. Fractions of 0 are off.
+    #[derive(Clone, Copy, Debug, Default, PartialEq)]
+    pub struct Stops {
+        /// Sell when the price falls this fraction below the entry price
+        pub loss: f64,
+        /// Sell when the price falls this fraction below its highest since entry
+        pub trailing: f64,
+        /// Sell when the price rises this fraction above the entry price
+        pub profit: f64,
+        /// Days to hold a position before the exit genes can sell it
+        pub hold: usize,
+    }
+
+    // Three genes joined by & and |, with the days each needs
+    #[derive(Clone, Copy)]
+    struct Genes {
+        indicators: (Indicator, Indicator, Indicator),
+        operators: (char, char),
+        days: (usize, usize, usize),
+    }
+
+    // The kind of each byte of a genotype with exits, which is the entry genes,
+    // the exit genes and the stops, separated by '/'
+    enum Locus {
+        Kind,
+        Digit,
+        Operator,
+        Fixed,
+        Direction,
+    }
+
+    impl Stops {
+        /// Reads stops written as l050t100p250h005: a stop-loss, trailing stop and
+        /// take-profit in tenths of a percent and a minimum holding period in days
+        pub fn parse(genes: &[u8]) -> Result<Stops, String> {
+            let text = String::from_utf8_lossy(genes);
+
+            if genes.len() != 16 {
+                return Err(format!(
+                    "stops {text} are {} bytes, expected 16 such as l050t100p250h005",
+                    genes.len()
+                ));
+            }
+
+            let value = |letter: u8| {
+                let gene = genes
+                    .chunks(4)
+                    .find(|g| g[0] == letter)
+                    .ok_or_else(|| format!("stops {text} have no {}", letter as char))?;
+
+                gene[1..].iter().try_fold(0, |n, d| match (*d as char).to_digit(10) {
+                    Some(d) => Ok(n * 10 + d as usize),
+                    None => Err(format!("stops {text} have {} where a digit goes", *d as char)),
+                })
+            };
+
+            Ok(Stops {
+                loss: value(b'l')? as f64 / 1000.0,
+                trailing: value(b't')? as f64 / 1000.0,
+                profit: value(b'p')? as f64 / 1000.0,
+                hold: value(b'h')?,
+            })
+        }
+
+        // Whether an open position should be closed at `price` regardless of the
+        // genes. Short positions lose when the price rises, so their stops are mirrored.
+        fn triggered(&self, actor: &Actor, price: f64) -> bool {
+            if actor.stocks < 0.0 {
+                (self.loss > 0.0 && price >= actor.entry * (1.0 + self.loss))
+                    || (self.trailing > 0.0 && price >= actor.peak * (1.0 + self.trailing))
+                    || (self.profit > 0.0 && price <= actor.entry * (1.0 - self.profit))
+            } else {
+                (self.loss > 0.0 && price <= actor.entry * (1.0 - self.loss))
+                    || (self.trailing > 0.0 && price <= actor.peak * (1.0 - self.trailing))
+                    || (self.profit > 0.0 && price >= actor.entry * (1.0 + self.profit))
+            }
+        }
+
+        // Random stops, each off a third of the time
+        fn generate(rng: &mut impl Rng) -> Vec<u8> {
+            let mut genes = Vec::with_capacity(16);
+
+            let ranges = [(b'l', 10..=200), (b't', 10..=200), (b'p', 20..=500), (b'h', 1..=20)];
+
+            for (letter, range) in ranges {
+                let value: usize = if rng.gen_bool(1.0 / 3.0) { 0 } else { rng.gen_range(range) };
+                genes.push(letter);
+                genes.extend(format!("{value:03}").bytes());
+            }
+
+            genes
+        }
+    }
+
+    impl Direction {
+        /// Reads a direction gene: l for long, s for short or b for both
+        pub fn parse(gene: u8) -> Result<Direction, String> {
+            match gene {
+                b'l' => Ok(Direction::Long),
+                b's' => Ok(Direction::Short),
+                b'b' => Ok(Direction::Both),
+                _ => Err(format!("unknown direction {}, expected l, s or b", gene as char)),
+            }
+        }
+
+        /// The direction's gene
+        pub fn gene(&self) -> u8 {
+            match self {
+                Direction::Long => b'l',
+                Direction::Short => b's',
+                Direction::Both => b'b',
+            }
+        }
+    }
+
+    impl Genes {
+        fn new(condition: &Condition) -> Genes {
+            let [a, b, c] = condition.indicators;
+
+            Genes {
+                indicators: (a, b, c),
+                operators: (condition.operators[0] as char, condition.operators[1] as char),
+                days: (a.days(), b.days(), c.days()),
+            }
+        }
+
+        // The first day any gene has a value
+        fn lowest(&self) -> usize {
+            self.days.0.min(self.days.1).min(self.days.2)
+        }
+
+        fn applicable(&self) -> bool {
+            self.days.0.max(self.days.1).max(self.days.2) != 0
+        }
+
+        fn ready(&self, day: usize) -> bool {
+            self.days.0 <= day || self.days.1 <= day || self.days.2 <= day
+        }
+
+        // The series of each gene on history `ticker`
+        fn series(&self, market: &Market, ticker: usize) -> (Series, Series, Series) {
+            (
+                market.series(ticker, self.indicators.0),
+                market.series(ticker, self.indicators.1),
+                market.series(ticker, self.indicators.2),
+            )
+        }
+
+        // What each gene says about `day`, from its series
+        fn readings(
+            &self,
+            series: &(Series, Series, Series),
+            history: &History,
+            day: usize,
+        ) -> Readings {
+            let price = history.close[day];
+
+            (
+                self.indicators.0.reading(series.0[day], price),
+                self.indicators.1.reading(series.1[day], price),
+                self.indicators.2.reading(series.2[day], price),
+            )
+        }
+
+        // Whether the genes hold on `day`, given what they say about it
+        fn holds(&self, genes: Readings, day: usize) -> bool {
+            let days = self.days;
+
+            (days.0 != 0 || days.1 != 0 || days.2 != 0)
+                && if self.operators.0 == '&' {
+                    if self.operators.1 == '&' {
+                        days.0 <= day
+                            && genes.0.bullish
+                            && days.1 <= day
+                            && genes.1.bullish
+                            && days.2 <= day
+                            && genes.2.bullish
+                    } else {
+                        days.0 <= day
+                            && genes.0.bullish
+                            && days.1 <= day
+                            && genes.1.bullish
+                            && days.2 <= day
+                            && genes.2.bullish
+                            && genes.2.value.is_some()
+                    }
+                } else {
+                    if self.operators.1 == '|' {
+                        (days.0 <= day && genes.0.bullish && genes.0.value.is_some())
+                            || (days.1 <= day && genes.1.bullish && genes.1.value.is_some())
+                            || (days.2 <= day && genes.2.bullish && genes.2.value.is_some())
+                    } else {
+                        (days.0 <= day && genes.0.bullish && genes.0.value.is_some())
+                            || (days.1 <= day && genes.1.bullish && genes.1.value.is_some())
+                            || (days.2 <= day && genes.2.bullish)
+                    }
+                }
+        }
+
+        // What to do on `day` when the entry genes say `buy` and any exit genes
+        // `sell`. The entry genes take precedence over the exit ones, and without
+        // exit genes a strategy sells once its genes can hold but don't.
+        fn signal(&self, buy: bool, sell: Option<bool>, day: usize) -> Signal {
+            match sell {
+                _ if buy => Signal::Buy,
+                Some(true) => Signal::Sell,
+                Some(false) => Signal::Hold,
+                None if self.ready(day) => Signal::Sell,
+                None => Signal::Hold,
+            }
+        }
+    }
+
+    impl Locus {
+        // The kind of byte `i` of the genes, exit genes and stops
+        fn of(i: usize) -> Locus {
+            match i {
+                0..=13 | 15..=28 => match (i % 15) % 5 {
+                    0 => Locus::Kind,
+                    4 => Locus::Operator,
+                    _ => Locus::Digit,
+                },
+                14 | 29 => Locus::Fixed,
+                _ if (i - 30).is_multiple_of(4) => Locus::Fixed,
+                _ => Locus::Digit,
+            }
+        }
     }
 
     impl Average {
+        pub(crate) fn moving(&self) -> Moving {
+            match *self {
+                Average::Simple(days) => Moving::Simple(Sma::new(days)),
+                Average::Exponential(days) => Moving::Exponential(Ema::new(days)),
+                Average::Maximum(days) => Moving::Maximum(Extreme::max(days)),
+                Average::Minimum(days) => Moving::Minimum(Extreme::min(days)),
+                Average::Weighted(days) => Moving::Weighted(Wma::new(days)),
+            }
+        }
+
         fn unwrap(&self) -> usize {
             match self {
                 Average::Simple(x) => *x,
                 Average::Exponential(x) => *x,
                 Average::Maximum(x) => *x,
+                Average::Minimum(x) => *x,
+                Average::Weighted(x) => *x,
             }
         }
     }
 
-    impl Market {
-        fn parse(chunk: [u8; 4]) -> Average {
-            let days = ((chunk[1] as char).to_digit(10).expect("Invalid genotype!") * 100
-                + (chunk[2] as char).to_digit(10).expect("Invalid genotype!") * 10
-                + (chunk[3] as char).to_digit(10).expect("Invalid genotype!"))
-                as usize;
+    impl Indicator {
+        /// Days of history needed before the indicator has a value
+        pub(crate) fn days(&self) -> usize {
+            match self {
+                Indicator::Average(a) => a.unwrap(),
+                Indicator::Rsi { days, .. } => *days,
+                Indicator::Macd(days) => Indicator::macd_days(*days).map_or(0, |(_, s, g)| s + g),
+                Indicator::Bollinger { days, .. } => *days,
+                Indicator::RateOfChange { days, .. } => *days,
+                Indicator::Volume { days, .. } => *days,
+            }
+        }
 
-            match chunk[0] {
-                b's' => Average::Simple(days),
-                b'e' => Average::Exponential(days),
-                b'm' => Average::Maximum(days),
-                _ => panic!("Invalid average type"),
+        /// The days the indicator is over, as written in its gene
+        pub(crate) fn period(&self) -> usize {
+            match self {
+                Indicator::Average(a) => a.unwrap(),
+                Indicator::Macd(days) => *days,
+                _ => self.days(),
             }
         }
 
-        fn get_average(
-            stock: &Vec<f64>,
-            day: usize,
-            average: &Average,
-            previous: Option<f64>,
-            denom: Option<f64>,
-        ) -> f64 {
-            match average {
-                Average::Simple(days) => {
-                    if day < *days || *days == 0 {
-                        0.0
-                    } else {
-                        if let Some(p) = previous {
-                            (p * *days as f64 + stock[day - 1] - stock[day - days - 1])
-                                / (*days as f64)
-                        } else {
-                            stock[(day - days)..day].iter().sum::<f64>() / *days as f64
-                        }
-                    }
-                }
-                Average::Exponential(days) => {
-                    if day < *days || day == 0 {
-                        0.0
-                    } else {
-                        let a = 1.0 - 2.0 / (*days as f64 + 1.0);
+        // The fast, slow and signal days of a MACD
+        fn macd_days(days: usize) -> Option<(usize, usize, usize)> {
+            (days > 1).then(|| ((days / 2).max(1), days, (days / 3).max(1)))
+        }
 
-                        if let Some(p) = previous {
-                            let d = denom
-                                .expect("Denominator must be provided for exponential average");
+        /// The indicator's value on `day`. Averages are over the days before it, like
+        /// `Market::get_average`, and the others include it.
+        pub(crate) fn value(&self, history: &History, day: usize) -> Option<f64> {
+            let stock = &history.close;
 
-                            (((p * d - (stock[day - days - 1] * a.powf(*days as f64))) * a) + stock[day - 1]) / d
-                        } else {
-                            let (n, d, _) = stock[(day - days)..day]
-                                .iter()
-                                .rev()
-                                .fold((0.0, 0.0, 0.0), |(n, d, x), p| {
-                                    (n + (p * a.powf(x)), d + a.powf(x), x + 1.0)
-                                });
-
-                            if d == 0.0 {
-                                0.0
+            match *self {
+                Indicator::Average(average) => {
+                    Some(Market::get_average(stock, day, &average))
+                }
+                _ if self.days() == 0 || day < self.days() || day >= stock.len() => None,
+                Indicator::Rsi { days, .. } => {
+                    let (gains, losses) = stock[day - days..=day]
+                        .windows(2)
+                        .map(|p| p[1] - p[0])
+                        .fold((0.0, 0.0), |(g, l), c| {
+                            if c > 0.0 {
+                                (g + c, l)
                             } else {
-                                n / d
+                                (g, l - c)
                             }
-                        }
+                        });
+
+                    if gains + losses == 0.0 {
+                        Some(50.0)
+                    } else {
+                        Some(100.0 * gains / (gains + losses))
                     }
                 }
-                Average::Maximum(days) => {
-                    if day < *days || day == 0 {
-                        0.0
-                    } else {
-                        if let Some(p) = previous {
-                            if stock[day - days - 1] != p {
-                                return p.max(stock[day - 1]);
-                            }
-                        }
+                Indicator::Macd(days) => {
+                    let (fast, slow, signal) = Indicator::macd_days(days)?;
+                    let mut emas = Indicator::macd_start(stock[0]);
 
-                        *stock[(day - *days)..day]
-                            .iter()
-                            .max_by(|a, b| a.partial_cmp(b).unwrap())
-                            .unwrap_or(&0.0)
+                    for price in &stock[1..=day] {
+                        emas = Indicator::macd_step(emas, *price, fast, slow, signal);
+                    }
+
+                    Some(emas.0 - emas.1 - emas.2)
+                }
+                Indicator::Bollinger { days, .. } => {
+                    let window = &stock[day + 1 - days..=day];
+                    let mean = window.iter().sum::<f64>() / days as f64;
+                    let deviation = (window.iter().map(|p| (p - mean).powi(2)).sum::<f64>()
+                        / days as f64)
+                        .sqrt();
+
+                    if deviation == 0.0 {
+                        Some(0.0)
+                    } else {
+                        Some((stock[day] - mean) / deviation)
                     }
                 }
+                Indicator::RateOfChange { days, .. } => {
+                    let then = stock[day - days];
+                    (then != 0.0).then(|| (stock[day] / then - 1.0) * 100.0)
+                }
+                Indicator::Volume { days, .. } => {
+                    let volume = history.volume.get(day + 1 - days..=day)?;
+                    let average = history.volume[day - days..day].iter().sum::<f64>() / days as f64;
+                    (average != 0.0).then(|| volume[days - 1] / average)
+                }
             }
         }
 
-        fn buy(actor: &mut Actor, price: f64) {
-            if actor.capital + actor.gains < price {
-                return;
+        /// The letter of the indicator's gene
+        pub(crate) fn kind(&self) -> u8 {
+            match self {
+                Indicator::Average(Average::Simple(_)) => b's',
+                Indicator::Average(Average::Exponential(_)) => b'e',
+                Indicator::Average(Average::Maximum(_)) => b'm',
+                Indicator::Average(Average::Minimum(_)) => b'n',
+                Indicator::Average(Average::Weighted(_)) => b'w',
+                Indicator::Rsi { .. } => b'r',
+                Indicator::Macd(_) => b'c',
+                Indicator::Bollinger { .. } => b'b',
+                Indicator::RateOfChange { .. } => b'o',
+                Indicator::Volume { .. } => b'v',
             }
+        }
 
-            if actor.capital < 20000.0 {
-                if actor.gains > 20000.0 {
-                    actor.gains -= 20000.0;
-                    actor.capital += 20000.0;
-                    trace!("Borrowing $20,000 from gains");
-                } else if actor.gains > 0.0 {
-                    trace!("Borrowing ${} from gains", actor.gains);
-                    actor.capital += actor.gains;
-                    actor.gains = 0.0;
-                }
-            }
+        /// The indicator's value on every day of `history`
+        pub(crate) fn series(&self, history: &History) -> Vec<Option<f64>> {
+            let mut reader = Reader::new(*self);
+            (0..history.len()).map(|day| reader.read(history, day).value).collect()
+        }
 
-            let shares = (actor.capital / price) as usize;
-            actor.capital -= shares as f64 * price;
-            actor.stocks += shares;
-            trace!(
-                "Purchased {shares} stocks at ${price} a share to lose ${:.2}",
-                shares as f64 * price
-            );
-            trace!(
-                "{} shares, ${:.2} in capital, ${:.2} in gains",
-                actor.stocks,
-                actor.capital,
-                actor.gains
-            );
+        // What the indicator says about a day with `price`, given its value then.
+        // Averages without enough days have always compared as 0.
+        fn reading(&self, value: Option<f64>, price: f64) -> Reading {
+            let bullish = match self {
+                Indicator::Average(_) => value.unwrap_or(0.0) < price,
+                _ => value.is_some_and(|v| self.bullish(v, price)),
+            };
+
+            Reading { bullish, value }
         }
 
-        fn sell(actor: &mut Actor, price: f64) {
-            if actor.stocks == 0 {
-                return;
+        fn bullish(&self, value: f64, price: f64) -> bool {
+            match *self {
+                Indicator::Average(_) => value < price,
+                Indicator::Rsi { level, .. } => value > level,
+                Indicator::Macd(_) => value > 0.0,
+                Indicator::Bollinger { deviations, .. } => value > deviations,
+                Indicator::RateOfChange { percent, .. } => value > percent,
+                Indicator::Volume { ratio, .. } => value > ratio,
             }
+        }
 
-            let shares = actor.stocks;
-            actor.gains += shares as f64 * price;
-            actor.stocks = 0;
-            trace!(
-                "Sold {shares} shares at ${price} to gain ${:.2}",
-                shares as f64 * price
-            );
-            trace!(
-                "{} shares, ${:.2} in capital, ${:.2} in gains",
-                actor.stocks,
-                actor.capital,
-                actor.gains
-            );
+        // The fast, slow and signal EMAs of a MACD on its first day
+        fn macd_start(price: f64) -> (f64, f64, f64) {
+            (price, price, 0.0)
         }
-    }
 
-    impl Problem for Market {
-        fn fitness(&self, genotype: &Vec<u8>) -> Fitness {
-            debug!("Evaluating {}", genotype.iter().map(|x| *x as char).collect::<String>());
-            let strategy = (
-                Market::parse(genotype[0..4].try_into().expect("Invalid genotype!")),
-                genotype[4] as char,
-                Market::parse(genotype[5..9].try_into().expect("Invalid genotype!")),
-                genotype[9] as char,
-                Market::parse(genotype[10..14].try_into().expect("Invalid genotype!")),
-            );
-            let days = (
-                strategy.0.unwrap(),
-                strategy.2.unwrap(),
-                strategy.4.unwrap(),
-            );
-            let lowest = days.0.min(days.1).min(days.2);
-            let denoms = (
-                if let Average::Exponential(_) = strategy.0 {
-                    (0..days.0)
-                        .fold(
-                            (0.0, 1.0 - 2.0 / (days.0 as f64 + 1.0), 0.0),
-                            |(d, a, x), _| (d + (a).powf(x), a, x + 1.0),
-                        )
-                        .0
-                } else {
-                    0.0
-                },
-                if let Average::Exponential(_) = strategy.2 {
-                    (0..days.1)
-                        .fold(
-                            (0.0, 1.0 - 2.0 / (days.1 as f64 + 1.0), 0.0),
-                            |(d, a, x), _| (d + (a).powf(x), a, x + 1.0),
-                        )
-                        .0
-                } else {
-                    0.0
-                },
-                if let Average::Exponential(_) = strategy.4 {
-                    (0..days.2)
-                        .fold(
-                            (0.0, 1.0 - 2.0 / (days.2 as f64 + 1.0), 0.0),
-                            |(d, a, x), _| (d + (a).powf(x), a, x + 1.0),
-                        )
-                        .0
-                } else {
-                    0.0
-                },
-            );
+        fn macd_step(
+            (fast, slow, signal): (f64, f64, f64),
+            price: f64,
+            fast_days: usize,
+            slow_days: usize,
+            signal_days: usize,
+        ) -> (f64, f64, f64) {
+            let ema = |previous: f64, value: f64, days: usize| {
+                let a = 2.0 / (days as f64 + 1.0);
+                previous + a * (value - previous)
+            };
 
-            if days.0.max(days.1).max(days.2) == 0 {
-                trace!("No applicable strategies");
-                return Fitness::Valid(0.0);
+            let (fast, slow) = (ema(fast, price, fast_days), ema(slow, price, slow_days));
+            (fast, slow, ema(signal, fast - slow, signal_days))
+        }
+    }
+
+    impl Reader {
+        pub(crate) fn new(indicator: Indicator) -> Reader {
+            Reader {
+                indicator,
+                average: None,
+                macd: None,
             }
+        }
 
-            let funds = &self.histories.par_iter().fold(|| 0.0, |profit, stock| {
-                let mut actor = Actor {
-                    capital: self.funds,
-                    gains: 0.0,
-                    stocks: 0,
-                    strategy,
-                };
+        /// Called for consecutive days, so averages and MACDs can be updated
+        pub(crate) fn read(&mut self, history: &History, day: usize) -> Reading {
+            let stock = &history.close;
 
-                let mut avgs = (0.0, 0.0, 0.0);
-
-                for day in lowest..stock.len() - 1 {
-                    avgs = (
-                        Market::get_average(
-                            stock,
-                            day,
-                            &actor.strategy.0,
-                            if avgs.0 == 0.0 { None } else { Some(avgs.0) },
-                            if avgs.0 == 0.0 { None } else { Some(denoms.0) },
-                        ),
-                        Market::get_average(
-                            stock,
-                            day,
-                            &actor.strategy.2,
-                            if avgs.1 == 0.0 { None } else { Some(avgs.1) },
-                            if avgs.1 == 0.0 { None } else { Some(denoms.1) },
-                        ),
-                        Market::get_average(
-                            stock,
-                            day,
-                            &actor.strategy.4,
-                            if avgs.2 == 0.0 { None } else { Some(avgs.2) },
-                            if avgs.2 == 0.0 { None } else { Some(denoms.2) },
-                        ),
-                    );
+            match self.indicator {
+                Indicator::Average(average) => {
+                    let days = average.unwrap();
 
-                    let buy = {
-                        (days.0 != 0 || days.1 != 0 || days.2 != 0)
-                            && if actor.strategy.1 == '&' {
-                                if actor.strategy.3 == '&' {
-                                    days.0 <= day
-                                        && avgs.0 < stock[day]
-                                        && days.1 <= day
-                                        && avgs.1 < stock[day]
-                                        && days.2 <= day
-                                        && avgs.2 < stock[day]
-                                } else {
-                                    days.0 <= day
-                                        && avgs.0 < stock[day]
-                                        && days.1 <= day
-                                        && avgs.1 < stock[day]
-                                        && days.2 <= day
-                                        && avgs.2 < stock[day]
-                                        && avgs.2 != 0.0
-                                }
-                            } else {
-                                if actor.strategy.3 == '|' {
-                                    (days.0 <= day && avgs.0 < stock[day] && avgs.0 != 0.0)
-                                        || (days.1 <= day && avgs.1 < stock[day] && avgs.1 != 0.0)
-                                        || (days.2 <= day && avgs.2 < stock[day] && avgs.2 != 0.0)
-                                } else {
-                                    (days.0 <= day && avgs.0 < stock[day] && avgs.0 != 0.0)
-                                        || (days.1 <= day && avgs.1 < stock[day] && avgs.1 != 0.0)
-                                        || (days.2 <= day && avgs.2 < stock[day])
-                                }
-                            }
+                    // start again from the average's window unless it's been given
+                    // the prices up to a day within it
+                    let stale = match self.average {
+                        Some((_, given)) => given > day || day - given > days,
+                        None => true,
                     };
 
-                    if buy {
-                        //trace!("Average: {:.2}, {:.2}, {:.2}", avgs.0, avgs.1, avgs.2);
-                        Market::buy(&mut actor, stock[day]);
-                    } else if days.0 <= day || days.1 <= day || days.2 <= day {
-                        //trace!("Average: {:.2}, {:.2}, {:.2}", avgs.0, avgs.1, avgs.2);
-                        Market::sell(&mut actor, stock[day]);
+                    if stale {
+                        self.average = Some((average.moving(), day.saturating_sub(days)));
                     }
+
+                    let (moving, given) = self.average.as_mut().unwrap();
+
+                    for price in &stock[*given..day] {
+                        moving.push(*price);
+                    }
+                    *given = day;
+
+                    self.indicator.reading(moving.value(), stock[day])
                 }
+                Indicator::Macd(days) => {
+                    let Some((fast, slow, signal)) = Indicator::macd_days(days) else {
+                        return Reading {
+                            bullish: false,
+                            value: None,
+                        };
+                    };
 
-                Market::sell(&mut actor, stock[stock.len() - 1]);
-                trace!(
-                    "{} Made ${:.2}",
-                    genotype.iter().map(|c| *c as char).collect::<String>(),
-                    actor.gains + actor.capital - self.funds
-                );
-                profit + actor.gains + actor.capital - self.funds
-            }).sum::<f64>();
+                    let emas = match self.macd {
+                        Some((last, f, s, g)) if last + 1 == day => {
+                            Indicator::macd_step((f, s, g), stock[day], fast, slow, signal)
+                        }
+                        _ => stock[1..=day]
+                            .iter()
+                            .fold(Indicator::macd_start(stock[0]), |emas, price| {
+                                Indicator::macd_step(emas, *price, fast, slow, signal)
+                            }),
+                    };
+                    self.macd = Some((day, emas.0, emas.1, emas.2));
 
-            let avg = funds / self.histories.len() as f64;
-            debug!("Average return: ${:.2}", avg);
-            debug!("Total return: ${:.2}", funds);
+                    let histogram = emas.0 - emas.1 - emas.2;
+                    let value = (day >= self.indicator.days()).then_some(histogram);
+                    self.indicator.reading(value, stock[day])
+                }
+                indicator => indicator.reading(indicator.value(history, day), stock[day]),
+            }
+        }
+    }
 
-            // Simple sigmoid function
-            Fitness::Valid(*funds)
+    impl History {
+        pub fn len(&self) -> usize {
+            self.close.len()
         }
 
-        fn mutate(&self, mutation_rate: f64, force_mutation: bool, g: &mut Genotype) {
-            let mut rng = rand::thread_rng();
-            let methods = [b's', b'e', b'm'];
+        pub fn is_empty(&self) -> bool {
+            self.close.is_empty()
+        }
+
+        /// The prices on `day` as a bar
+        pub fn bar(&self, day: usize) -> Bar {
+            Bar {
+                time: self.times.get(day).copied(),
+                open: self.open.get(day).copied(),
+                close: self.close[day],
+                volume: self.volume.get(day).copied(),
+            }
+        }
+
+        // Add a bar after the last. A column is only kept while every bar has it.
+        fn push(&mut self, bar: &Bar) {
+            let day = self.len();
+
+            if let (Some(time), true) = (bar.time, self.times.len() == day) {
+                if let Some(last) = self.times.last().filter(|last| **last >= time) {
+                    panic!("{} follows {}, times must be increasing", stamp(&time), stamp(last));
+                }
+
+                self.times.push(time);
+            }
+
+            for (column, value) in [(&mut self.open, bar.open), (&mut self.volume, bar.volume)] {
+                if let (Some(value), true) = (value, column.len() == day) {
+                    column.push(value);
+                }
+            }
+
+            self.close.push(bar.close);
+        }
+
+        /// The days from `from` to `to` that may be traded, keeping the days
+        /// before them for indicators
+        pub fn segment(&self, from: usize, to: usize) -> History {
+            let times = self.times[..to.min(self.times.len())].to_vec();
+
+            History {
+                ticker: self.ticker.clone(),
+                name: self.name.clone(),
+                range: match (times.get(from), times.last()) {
+                    (Some(first), Some(last)) => Some((first.date(), last.date())),
+                    _ => None,
+                },
+                order: self.order,
+                start: from,
+                times,
+                open: self.open[..to.min(self.open.len())].to_vec(),
+                high: self.high[..to.min(self.high.len())].to_vec(),
+                low: self.low[..to.min(self.low.len())].to_vec(),
+                close: self.close[..to].to_vec(),
+                volume: self.volume[..to.min(self.volume.len())].to_vec(),
+            }
+        }
+
+        // The first day at or after each fraction or date in `split`
+        fn boundaries(&self, split: &[String]) -> Vec<usize> {
+            let boundaries: Vec<usize> = split
+                .iter()
+                .map(|s| {
+                    if let Ok(fraction) = s.parse::<f64>() {
+                        if !(0.0..=1.0).contains(&fraction) {
+                            panic!("Invalid split {s}, expected a fraction between 0 and 1");
+                        }
+
+                        (self.len() as f64 * fraction).round() as usize
+                    } else if let Some(time) = parse_time(s) {
+                        if self.times.is_empty() {
+                            panic!("{} has no dates to split at {}", self.ticker, stamp(&time));
+                        }
+
+                        self.times.partition_point(|t| *t < time)
+                    } else {
+                        panic!("Invalid split {s}, expected a fraction or a date");
+                    }
+                })
+                .collect();
+
+            let mut bounds = vec![0];
+            bounds.extend(&boundaries);
+            bounds.push(self.len());
+
+            if bounds.windows(2).any(|b| b[0] >= b[1]) {
+                panic!(
+                    "Invalid split {} of {}, every part needs at least one day",
+                    split.join(" "),
+                    self.ticker
+                );
+            }
+
+            boundaries
+        }
+
+        /// Consecutive dates more than `days` apart
+        pub fn gaps(&self, days: i64) -> Vec<(NaiveDate, NaiveDate)> {
+            self.times
+                .windows(2)
+                .filter(|t| (t[1] - t[0]).num_days() > days)
+                .map(|t| (t[0].date(), t[1].date()))
+                .collect()
+        }
+
+        /// The history in bars of `frequency`, each with the first open, highest high,
+        /// lowest low, last close and total volume of the bars that start within it
+        pub fn resample(&self, frequency: Frequency) -> History {
+            if self.times.len() != self.len() {
+                panic!("{} has no times to resample", self.ticker);
+            }
+
+            let mut history = History {
+                ticker: self.ticker.clone(),
+                name: self.name.clone(),
+                range: self.range,
+                order: self.order,
+                ..Default::default()
+            };
+            let mut from = 0;
+
+            while from < self.len() {
+                let time = frequency.start(self.times[from]);
+                let within = self.times[from..].iter().take_while(|t| frequency.start(**t) == time);
+                let to = from + within.count();
+
+                history.times.push(time);
+                history.close.push(self.close[to - 1]);
+
+                if !self.open.is_empty() {
+                    history.open.push(self.open[from]);
+                }
+                if !self.high.is_empty() {
+                    history.high.push(self.high[from..to].iter().copied().fold(f64::MIN, f64::max));
+                }
+                if !self.low.is_empty() {
+                    history.low.push(self.low[from..to].iter().copied().fold(f64::MAX, f64::min));
+                }
+                if !self.volume.is_empty() {
+                    history.volume.push(self.volume[from..to].iter().sum());
+                }
+
+                from = to;
+            }
+
+            debug!("Resampled {} bars of {} into {}", self.len(), self.ticker, history.len());
+            history
+        }
+
+        /// Bars in a year, to annualize returns: trading days for daily bars and
+        /// histories without times, scaled by the bars in a day for shorter bars or
+        /// the days in a bar for longer ones
+        pub fn year(&self) -> f64 {
+            let (Some(first), Some(last)) = (self.times.first(), self.times.last()) else {
+                return TRADING_DAYS;
+            };
+
+            let days = 1 + self.times.windows(2).filter(|t| t[0].date() != t[1].date()).count();
+            let bars = self.times.len() as f64;
+
+            if days < self.times.len() {
+                TRADING_DAYS * bars / days as f64
+            } else {
+                // weekends and holidays make daily bars a day and a half apart
+                let apart = (*last - *first).num_days() as f64 / (bars - 1.0).max(1.0);
+
+                if apart <= MAX_GAP as f64 {
+                    TRADING_DAYS
+                } else {
+                    CALENDAR_DAYS / apart
+                }
+            }
+        }
+
+        /// Reads a plain-text or CSV history, oldest first and resampled as the options
+        /// say
+        pub fn read(file: &str, options: &Options) -> History {
+            let reader = match File::open(file) {
+                Ok(f) => BufReader::new(f),
+                Err(e) => {
+                    panic!("Error reading {}: {}", file, e);
+                }
+            };
+
+            let csv = Path::new(file)
+                .extension()
+                .is_some_and(|e| e.eq_ignore_ascii_case("csv"));
+
+            let mut history = if csv {
+                History::from_csv(file, reader, &options.columns)
+            } else {
+                History::from_text(file, reader, options.order)
+            };
+
+            // every day is counted back from the last
+            if history.is_empty() {
+                panic!("{file} contains no prices");
+            }
+
+            if history.ticker.is_empty() {
+                history.ticker = Path::new(file)
+                    .file_stem()
+                    .map_or(file.to_string(), |s| s.to_string_lossy().into_owned());
+            }
+
+            if history.order == Order::NewestFirst {
+                history.reverse();
+            }
+
+            if let Some(frequency) = options.resample {
+                history = history.resample(frequency);
+            }
+
+            debug!(
+                "Read {} bars of {} from {file}, listed {:?}",
+                history.len(),
+                history.ticker,
+                history.order
+            );
+            history
+        }
+
+        // "AAPL - Apple" and "January 2000 - December 2002" followed by
+        // one closing price per line
+        fn from_text(file: &str, reader: BufReader<File>, order: Option<Order>) -> History {
+            let mut history = History {
+                close: Vec::with_capacity(250),
+                ..Default::default()
+            };
+            let mut header = Vec::new();
+
+            for l in reader.lines().map_while(std::result::Result::ok) {
+                if let Ok(price) = l.trim().parse::<f64>() {
+                    history.close.push(price);
+                } else if history.close.is_empty() && !l.trim().is_empty() {
+                    header.push(l);
+                }
+            }
+
+            if let Some(title) = header.first() {
+                let (ticker, name) = title.split_once(" - ").unwrap_or((title, ""));
+                history.ticker = ticker.trim().to_string();
+                history.name = name.trim().to_string();
+            }
+
+            // the first side of the range is when the prices start
+            let spans = header.get(1).map(|r| {
+                r.split(" - ").map(parse_span).collect::<Option<Vec<_>>>()
+            });
+
+            let listed = match spans {
+                Some(Some(spans)) if !spans.is_empty() => {
+                    let (first, last) = (spans[0], spans[spans.len() - 1]);
+                    history.range = Some((first.0.min(last.0), first.1.max(last.1)));
+
+                    match first.0.cmp(&last.0) {
+                        std::cmp::Ordering::Less => Ok(Order::OldestFirst),
+                        std::cmp::Ordering::Greater => {
+                            Err("the date range is written newest first")
+                        }
+                        std::cmp::Ordering::Equal => {
+                            Err("the date range doesn't say which order they are in")
+                        }
+                    }
+                }
+                Some(_) => Err("the date range can't be read"),
+                None => Err("there is no date range"),
+            };
+
+            history.order = match (order, listed) {
+                (Some(order), _) => order,
+                (None, Ok(order)) => order,
+                (None, Err(reason)) => {
+                    warn!("{file}: {reason}, assuming the prices are oldest first (see --order)");
+                    Order::OldestFirst
+                }
+            };
+
+            history
+        }
+
+        // Date, Open, High, Low, Close, Adj Close, Volume
+        fn from_csv(file: &str, reader: BufReader<File>, columns: &[String]) -> History {
+            let mut lines = reader.lines().map_while(std::result::Result::ok);
+            let header = match lines.next() {
+                Some(l) => split(&l),
+                None => panic!("{file} is empty"),
+            };
+
+            let mut fields = [
+                ("date", "Date", false),
+                ("open", "Open", false),
+                ("high", "High", false),
+                ("low", "Low", false),
+                ("close", "Close", false),
+                ("volume", "Volume", false),
+            ];
+
+            for c in columns {
+                let (field, name) = c
+                    .split_once('=')
+                    .unwrap_or_else(|| panic!("Invalid column mapping {c}, expected FIELD=HEADER"));
+
+                match fields.iter_mut().find(|f| f.0.eq_ignore_ascii_case(field.trim())) {
+                    Some(f) => *f = (f.0, name.trim().trim_matches('"'), true),
+                    None => panic!(
+                        "Unknown column {field}, expected one of date, open, high, low, close, volume"
+                    ),
+                }
+            }
+
+            // date and close are required, anything else only if it was mapped
+            let index = fields.map(|(field, name, mapped)| {
+                let i = header.iter().position(|h| h.eq_ignore_ascii_case(name));
+
+                if i.is_none() && (mapped || field == "date" || field == "close") {
+                    panic!("{file} has no {name} column for {field}");
+                }
+
+                i
+            });
+
+            let mut history = History::default();
+
+            for (n, l) in lines.enumerate() {
+                // line numbers start at 1 and include the header
+                let n = n + 2;
+                let cells = split(&l);
+
+                if cells.iter().all(|c| c.is_empty()) {
+                    continue;
+                }
+
+                let cell = |i: usize| cells.get(i).map_or("", |c| c.as_str());
+                let date = cell(index[0].unwrap());
+                let time =
+                    parse_time(date).unwrap_or_else(|| panic!("{file}:{n}: invalid date {date}"));
+
+                let prices: Option<Vec<Option<f64>>> = index[1..]
+                    .iter()
+                    .map(|i| match i {
+                        Some(i) => cell(*i).parse::<f64>().ok().map(Some),
+                        None => Some(None),
+                    })
+                    .collect();
+
+                // exports mark days without trading as null
+                let Some(prices) = prices else {
+                    warn!("{file}:{n}: skipping {date} with missing prices");
+                    continue;
+                };
+
+                if let Some(last) = history.times.last() {
+                    let order = if *last < time {
+                        Order::OldestFirst
+                    } else {
+                        Order::NewestFirst
+                    };
+
+                    if *last == time || (history.times.len() > 1 && order != history.order) {
+                        panic!(
+                            "{file}:{n}: {date} follows {}, dates must be increasing or decreasing",
+                            stamp(last)
+                        );
+                    }
+
+                    history.order = order;
+                }
+
+                history.times.push(time);
+
+                for (column, price) in [
+                    &mut history.open,
+                    &mut history.high,
+                    &mut history.low,
+                    &mut history.close,
+                    &mut history.volume,
+                ]
+                .into_iter()
+                .zip(prices)
+                {
+                    if let Some(p) = price {
+                        column.push(p);
+                    }
+                }
+            }
+
+            history.range = match (history.times.first(), history.times.last()) {
+                (Some(first), Some(last)) => Some((first.min(last).date(), first.max(last).date())),
+                _ => None,
+            };
+
+            for (from, to) in history.gaps(MAX_GAP) {
+                warn!("{file}: no prices between {from} and {to}");
+            }
+
+            history
+        }
+
+        fn reverse(&mut self) {
+            for column in [
+                &mut self.open,
+                &mut self.high,
+                &mut self.low,
+                &mut self.close,
+                &mut self.volume,
+            ] {
+                column.reverse();
+            }
+
+            self.times.reverse();
+        }
+
+        // The price of a trade decided on `day`
+        fn price(&self, day: usize, trade_at_open: bool) -> f64 {
+            if trade_at_open {
+                self.open[day + 1]
+            } else {
+                self.close[day]
+            }
+        }
+
+        /// The standard deviation of daily returns over the days up to `day`
+        pub fn volatility(&self, day: usize) -> Option<f64> {
+            let prices = self.close.get(day.checked_sub(VOLATILITY_DAYS)?..=day)?;
+            let returns: Vec<f64> = prices.windows(2).map(|p| p[1] / p[0] - 1.0).collect();
+            let mean = returns.iter().sum::<f64>() / returns.len() as f64;
+            let variance =
+                returns.iter().map(|r| (r - mean).powi(2)).sum::<f64>() / returns.len() as f64;
+
+            Some(variance.sqrt())
+        }
+    }
+
+    // A date, or a date and time, in any of the formats exports use. Dates are at
+    // midnight.
+    fn parse_time(s: &str) -> Option<NaiveDateTime> {
+        TIME_FORMATS
+            .iter()
+            .find_map(|f| NaiveDateTime::parse_from_str(s, f).ok())
+            .or_else(|| {
+                DATE_FORMATS
+                    .iter()
+                    .find_map(|f| NaiveDate::parse_from_str(s, f).ok())
+                    .map(NaiveDateTime::from)
+            })
+    }
+
+    // A fraction of the portfolio for --fraction, which must buy something and can't
+    // spend more than all of it
+    fn fraction(s: &str) -> std::result::Result<f64, String> {
+        match s.parse::<f64>() {
+            Ok(f) if f > 0.0 && f <= 1.0 => Ok(f),
+            Ok(_) => Err("must be more than 0 and at most 1".to_string()),
+            Err(e) => Err(e.to_string()),
+        }
+    }
+
+    // A time, or only its date at midnight
+    fn stamp(time: &NaiveDateTime) -> String {
+        if time.time() == NaiveTime::MIN {
+            time.date().to_string()
+        } else {
+            time.to_string()
+        }
+    }
+
+    // The first and last day of "24 March 2023", "January 2000" or "Jan 2017"
+    fn parse_span(s: &str) -> Option<(NaiveDate, NaiveDate)> {
+        let s = s.trim();
+
+        if let Ok(day) = NaiveDate::parse_from_str(s, "%d %B %Y") {
+            return Some((day, day));
+        }
+
+        let first = NaiveDate::parse_from_str(&format!("1 {s}"), "%d %B %Y").ok()?;
+        let last = first.checked_add_months(Months::new(1))?.pred_opt()?;
+
+        Some((first, last))
+    }
+
+    /// Trading days in a year, to annualize daily figures
+    pub const TRADING_DAYS: f64 = 252.0;
+
+    // Days in a year, to annualize figures over bars longer than a day
+    const CALENDAR_DAYS: f64 = 365.25;
+
+    impl Objective {
+        pub fn score(&self, simulations: &[Simulation]) -> f64 {
+            let mean = |f: fn(&Simulation) -> f64| {
+                if simulations.is_empty() {
+                    0.0
+                } else {
+                    simulations.iter().map(f).sum::<f64>() / simulations.len() as f64
+                }
+            };
+
+            match self {
+                Objective::TotalReturn => simulations.iter().map(|s| s.profit).sum(),
+                Objective::Cagr => mean(Simulation::cagr),
+                Objective::Sharpe => mean(Simulation::sharpe),
+                Objective::Sortino => mean(Simulation::sortino),
+                Objective::Calmar => mean(Simulation::calmar),
+                Objective::MaxDrawdown => -mean(Simulation::max_drawdown),
+                Objective::ProfitPerTrade => {
+                    let trades = simulations.iter().map(|s| s.trades).sum::<usize>();
+
+                    if trades == 0 {
+                        0.0
+                    } else {
+                        simulations.iter().map(|s| s.profit).sum::<f64>() / trades as f64
+                    }
+                }
+            }
+        }
+
+        /// The objective on a single stock
+        pub fn measure(&self, simulation: &Simulation) -> f64 {
+            self.score(std::slice::from_ref(simulation))
+        }
+    }
+
+    impl Aggregate {
+        /// The objective on each ticker combined into one. Beating buying and holding
+        /// is counted by `Market::score`, which knows what buying and holding makes.
+        pub fn combine(&self, values: &[f64]) -> f64 {
+            if values.is_empty() {
+                return 0.0;
+            }
+
+            match self {
+                Aggregate::Mean | Aggregate::BeatHold => mean(values),
+                Aggregate::Median => {
+                    let mut sorted = values.to_vec();
+                    sorted.sort_by(f64::total_cmp);
+
+                    let middle = sorted.len() / 2;
+                    if sorted.len().is_multiple_of(2) {
+                        (sorted[middle - 1] + sorted[middle]) / 2.0
+                    } else {
+                        sorted[middle]
+                    }
+                }
+                Aggregate::Worst => values.iter().copied().fold(f64::INFINITY, f64::min),
+            }
+        }
+    }
+
+    impl Display for Aggregate {
+        fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+            match self {
+                Aggregate::Mean => write!(f, "mean"),
+                Aggregate::Median => write!(f, "median"),
+                Aggregate::Worst => write!(f, "worst"),
+                Aggregate::BeatHold => write!(f, "fraction beating buying and holding"),
+            }
+        }
+    }
+
+    impl Display for Objective {
+        fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+            match self {
+                Objective::TotalReturn => write!(f, "total return"),
+                Objective::Cagr => write!(f, "CAGR"),
+                Objective::Sharpe => write!(f, "Sharpe ratio"),
+                Objective::Sortino => write!(f, "Sortino ratio"),
+                Objective::Calmar => write!(f, "Calmar ratio"),
+                Objective::MaxDrawdown => write!(f, "maximum drawdown"),
+                Objective::ProfitPerTrade => write!(f, "profit per trade"),
+            }
+        }
+    }
+
+    impl std::str::FromStr for Frequency {
+        type Err = String;
+
+        // A number followed by m, h, d or w
+        fn from_str(s: &str) -> Result<Frequency, String> {
+            let digits = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+            let (count, unit) = s.split_at(digits);
+
+            match (count.parse::<u32>(), unit) {
+                (Ok(n), "m") if n > 0 => Ok(Frequency::Minutes(n)),
+                (Ok(n), "h") if n > 0 => Ok(Frequency::Hours(n)),
+                (Ok(n), "d") if n > 0 => Ok(Frequency::Days(n)),
+                (Ok(n), "w") if n > 0 => Ok(Frequency::Weeks(n)),
+                _ => Err(format!("Invalid frequency {s}, expected e.g. 15m, 1h, 1d or 1w")),
+            }
+        }
+    }
+
+    impl Frequency {
+        /// When the bar that `time` falls in starts. Bars within a day are counted
+        /// from midnight, and weeks start on Mondays.
+        pub fn start(&self, time: NaiveDateTime) -> NaiveDateTime {
+            // days since 1 January of year 1, which was a Monday
+            let day = time.num_days_from_ce() as i64 - 1;
+            let midnight = |day: i64| {
+                NaiveDate::from_num_days_from_ce_opt(day as i32 + 1)
+                    .expect("Date out of range")
+                    .and_time(NaiveTime::MIN)
+            };
+            let minutes = time.hour() as i64 * 60 + time.minute() as i64;
+
+            match *self {
+                Frequency::Minutes(n) => {
+                    midnight(day) + Duration::minutes(minutes - minutes % n as i64)
+                }
+                Frequency::Hours(n) => {
+                    midnight(day) + Duration::minutes(minutes - minutes % (n as i64 * 60))
+                }
+                Frequency::Days(n) => midnight(day - day % n as i64),
+                Frequency::Weeks(n) => midnight(day - day % (n as i64 * 7)),
+            }
+        }
+    }
+
+    impl Simulation {
+        /// The fractional change in equity from each day to the next
+        pub fn daily_returns(&self) -> Vec<f64> {
+            self.equity
+                .windows(2)
+                .map(|e| if e[0] == 0.0 { 0.0 } else { e[1] / e[0] - 1.0 })
+                .collect()
+        }
+
+        pub fn cagr(&self) -> f64 {
+            let (Some(first), Some(last)) = (self.equity.first(), self.equity.last()) else {
+                return 0.0;
+            };
+
+            if self.equity.len() < 2 || *first <= 0.0 {
+                0.0
+            } else if *last <= 0.0 {
+                -1.0
+            } else {
+                (last / first).powf(self.year / (self.equity.len() - 1) as f64) - 1.0
+            }
+        }
+
+        pub fn sharpe(&self) -> f64 {
+            let returns = self.daily_returns();
+            let mean = mean(&returns);
+            let deviation = (returns.iter().map(|r| (r - mean).powi(2)).sum::<f64>()
+                / returns.len().max(1) as f64)
+                .sqrt();
+
+            if deviation == 0.0 {
+                0.0
+            } else {
+                mean / deviation * self.year.sqrt()
+            }
+        }
+
+        pub fn sortino(&self) -> f64 {
+            let returns = self.daily_returns();
+            let deviation = (returns.iter().map(|r| r.min(0.0).powi(2)).sum::<f64>()
+                / returns.len().max(1) as f64)
+                .sqrt();
+
+            if deviation == 0.0 {
+                0.0
+            } else {
+                mean(&returns) / deviation * self.year.sqrt()
+            }
+        }
+
+        /// The largest fall from a peak as a fraction of the peak
+        pub fn max_drawdown(&self) -> f64 {
+            self.equity
+                .iter()
+                .scan(f64::MIN, |peak, e| {
+                    *peak = peak.max(*e);
+                    Some(if *peak > 0.0 { (*peak - e) / *peak } else { 0.0 })
+                })
+                .fold(0.0, f64::max)
+        }
+
+        pub fn calmar(&self) -> f64 {
+            let drawdown = self.max_drawdown();
+
+            if drawdown == 0.0 {
+                0.0
+            } else {
+                self.cagr() / drawdown
+            }
+        }
+    }
+
+    // Dollars made across every stock, or by the portfolio
+    fn profit(outcome: &Outcome) -> f64 {
+        match &outcome.account {
+            Some(account) => account.profit,
+            None => outcome.stocks.iter().map(|s| s.profit).sum(),
+        }
+    }
+
+    // The maximum drawdown of the portfolio, or averaged across stocks
+    fn drawdown(outcome: &Outcome) -> f64 {
+        match &outcome.account {
+            Some(account) => account.max_drawdown(),
+            None => mean(&outcome.stocks.iter().map(Simulation::max_drawdown).collect::<Vec<_>>()),
+        }
+    }
+
+    impl Bootstrap {
+        /// The range of values in the middle `confidence` of the samples
+        pub fn interval(values: &[f64], confidence: f64) -> (f64, f64) {
+            if values.is_empty() {
+                return (0.0, 0.0);
+            }
+
+            let mut sorted = values.to_vec();
+            sorted.sort_by(f64::total_cmp);
+
+            let tail = (1.0 - confidence.clamp(0.0, 1.0)) / 2.0;
+            let rank = |q: f64| sorted[((sorted.len() - 1) as f64 * q).round() as usize];
+            (rank(tail), rank(1.0 - tail))
+        }
+
+        /// The fraction of samples on which the strategy beat buying and holding
+        pub fn beat_chance(&self) -> f64 {
+            if self.beaten.is_empty() {
+                0.0
+            } else {
+                self.beaten.iter().filter(|b| **b).count() as f64 / self.beaten.len() as f64
+            }
+        }
+
+        /// The confidence intervals on returns and drawdowns, and the chance of beating
+        /// buying and holding
+        pub fn describe(&self, confidence: f64) -> String {
+            let returns = Bootstrap::interval(&self.returns, confidence);
+            let drawdowns = Bootstrap::interval(&self.drawdowns, confidence);
+
+            format!(
+                "{:.0}% of {} samples made ${:.2} to ${:.2} with maximum drawdowns of {:.1}% to \
+                 {:.1}%, and {:.1}% beat buying and holding",
+                confidence * 100.0,
+                self.returns.len(),
+                returns.0,
+                returns.1,
+                drawdowns.0 * 100.0,
+                drawdowns.1 * 100.0,
+                self.beat_chance() * 100.0
+            )
+        }
+    }
+
+    fn mean(values: &[f64]) -> f64 {
+        if values.is_empty() {
+            0.0
+        } else {
+            values.iter().sum::<f64>() / values.len() as f64
+        }
+    }
+
+    fn split(line: &str) -> Vec<String> {
+        line.split(',')
+            .map(|c| c.trim().trim_matches('"').to_string())
+            .collect()
+    }
+
+    impl Market {
+        /// Read the histories in `files`, which are CSV exports if they end in .csv
+        /// and one closing price per line otherwise
+        pub fn with_options(files: Vec<String>, options: Options) -> Market {
+            let histories: Vec<History> = files
+                .iter()
+                .map(|file| {
+                    let history = History::read(file, &options);
+
+                    if options.trade_at_open && history.open.len() != history.len() {
+                        panic!("{file} has no open prices to trade at");
+                    }
+
+                    history
+                })
+                .collect();
+
+            let mut market = Market {
+                alphabet: vec![
+                    b'&', b'|', b's', b'e', b'm', b'n', b'w', b'r', b'c', b'b', b'o', b'v', b'0',
+                    b'1', b'2', b'3', b'4', b'5', b'6', b'7', b'8', b'9', b'/', b'l', b't', b'p',
+                    b'h',
+                ],
+                funds: 20000.0,
+                histories,
+                options,
+                held_out: Vec::new(),
+                held: OnceLock::new(),
+                cache: Cache::default(),
+            };
+
+            if !market.options.split.is_empty() {
+                let parts: Vec<Vec<History>> = market
+                    .histories
+                    .iter()
+                    .map(|h| {
+                        let mut bounds = vec![0];
+                        bounds.extend(h.boundaries(&market.options.split));
+                        bounds.push(h.len());
+
+                        bounds.windows(2).map(|b| h.segment(b[0], b[1])).collect()
+                    })
+                    .collect();
+
+                let names: &[&str] = if market.options.split.len() == 1 {
+                    &["test"]
+                } else {
+                    &["validation", "test"]
+                };
+
+                for (i, name) in names.iter().enumerate() {
+                    let histories = parts.iter().map(|p| p[i + 1].clone()).collect();
+                    market.held_out.push((name.to_string(), market.with(histories)));
+                }
+
+                market.histories = parts.into_iter().map(|p| p[0].clone()).collect();
+            }
+
+            if !market.options.test_file.is_empty() {
+                let files = market.options.test_file.clone();
+                let options = Options {
+                    split: Vec::new(),
+                    test_file: Vec::new(),
+                    ..market.options.clone()
+                };
+
+                market
+                    .held_out
+                    .push(("held-out".to_string(), Market::with_options(files, options)));
+            }
+
+            market
+        }
+
+        // A market with the same settings trading other histories
+        fn with(&self, histories: Vec<History>) -> Market {
+            Market {
+                alphabet: self.alphabet.clone(),
+                histories,
+                funds: self.funds,
+                options: self.options.clone(),
+                held_out: Vec::new(),
+                held: OnceLock::new(),
+                cache: Cache::default(),
+            }
+        }
+
+        /// Rolling walk-forward windows of `--walk-forward TRAIN TEST` days, as markets
+        /// to train on and markets to test on. Every history is cut at the same days,
+        /// as far as the shortest one allows.
+        pub fn windows(&self) -> Vec<(Market, Market)> {
+            let [train, test] = self.options.walk_forward[..] else {
+                panic!("Walk-forward windows need TRAIN and TEST days");
+            };
+
+            if train == 0 || test == 0 {
+                panic!("Walk-forward windows need at least one day to train and test on");
+            }
+
+            let days = self.histories.iter().map(|h| h.len()).min().unwrap_or(0);
+
+            (0..)
+                .map(|w| w * test)
+                .take_while(|from| from + train + test <= days)
+                .map(|from| {
+                    let segment = |from, to| {
+                        self.histories
+                            .iter()
+                            .map(|h| h.segment(from, to))
+                            .collect::<Vec<History>>()
+                    };
+
+                    (
+                        self.with(segment(from, from + train)),
+                        self.with(segment(from + train, from + train + test)),
+                    )
+                })
+                .collect()
+        }
+
+        /// Reads a gene: averages take three digits of days, and the others a digit for
+        /// their threshold followed by two of days
+        pub fn parse(gene: [u8; 4]) -> Result<Indicator, String> {
+            let Some(digits) = gene[1..]
+                .iter()
+                .map(|d| (*d as char).to_digit(10).map(|d| d as usize))
+                .collect::<Option<Vec<usize>>>()
+            else {
+                return Err(format!(
+                    "gene {} needs three digits after its letter",
+                    String::from_utf8_lossy(&gene)
+                ));
+            };
+            let days = digits[0] * 100 + digits[1] * 10 + digits[2];
+            let (level, period) = (digits[0] as f64, digits[1] * 10 + digits[2]);
+
+            Ok(match gene[0] {
+                b's' => Indicator::Average(Average::Simple(days)),
+                b'e' => Indicator::Average(Average::Exponential(days)),
+                b'm' => Indicator::Average(Average::Maximum(days)),
+                b'n' => Indicator::Average(Average::Minimum(days)),
+                b'w' => Indicator::Average(Average::Weighted(days)),
+                b'r' => Indicator::Rsi {
+                    days: period,
+                    level: level * 10.0,
+                },
+                b'c' => Indicator::Macd(days),
+                b'b' => Indicator::Bollinger {
+                    days: period,
+                    deviations: (level - 5.0) / 2.0,
+                },
+                b'o' => Indicator::RateOfChange {
+                    days: period,
+                    percent: level - 5.0,
+                },
+                b'v' => Indicator::Volume {
+                    days: period,
+                    ratio: level / 5.0,
+                },
+                kind => {
+                    return Err(format!(
+                        "unknown indicator {} in gene {}, expected s, e, w, m, n, r, c, b, o or v",
+                        kind as char,
+                        String::from_utf8_lossy(&gene)
+                    ))
+                }
+            })
+        }
+
+        /// The kinds of gene that can be used on these histories
+        pub fn methods(&self) -> Vec<u8> {
+            let mut methods = b"semnwrcbo".to_vec();
+
+            if !self.histories.is_empty() && self.histories.iter().all(|h| !h.volume.is_empty()) {
+                methods.push(b'v');
+            }
+
+            methods
+        }
+
+        // Three random genes of the kinds in `methods`, joined by random operators
+        fn generate_genes(methods: &[u8], rng: &mut impl Rng) -> Vec<u8> {
+            let operators = [b'&', b'|'];
+            let mut genes = Vec::with_capacity(14);
+
+            for i in 0..3 {
+                if i > 0 {
+                    genes.push(operators[rng.gen_range(0..=1)]);
+                }
+
+                let kind = methods[rng.gen_range(0..methods.len())];
+                genes.extend(Market::generate_gene(kind, rng));
+            }
+
+            genes
+        }
+
+        // A random gene of `kind`, with parameters in a sensible range for it
+        pub(crate) fn generate_gene(kind: u8, rng: &mut impl Rng) -> [u8; 4] {
+            let (level, days) = match kind {
+                b'r' => (rng.gen_range(2..=7), rng.gen_range(2..=30)),
+                b'c' => (0, rng.gen_range(6..=60)),
+                b'b' | b'o' => (rng.gen_range(0..=9), rng.gen_range(2..=40)),
+                b'v' => (rng.gen_range(3..=9), rng.gen_range(5..=40)),
+                _ => (rng.gen_range(0..2), rng.gen_range(0..2) * 10 + rng.gen_range(0..=9)),
+            };
+
+            [kind, b'0' + level, b'0' + days / 10, b'0' + days % 10]
+        }
+
+        /// The average of the days before `day` computed from scratch, or 0 until
+        /// there are enough of them. Genes read the same averages a day at a time.
+        pub(crate) fn get_average(stock: &[f64], day: usize, average: &Average) -> f64 {
+            match average {
+                Average::Simple(days) => {
+                    if day < *days || *days == 0 {
+                        0.0
+                    } else {
+                        stock[(day - days)..day].iter().sum::<f64>() / *days as f64
+                    }
+                }
+                Average::Exponential(days) => {
+                    if day < *days || day == 0 {
+                        0.0
+                    } else {
+                        let a = 1.0 - 2.0 / (*days as f64 + 1.0);
+                        let (n, d, _) = stock[(day - days)..day]
+                            .iter()
+                            .rev()
+                            .fold((0.0, 0.0, 0.0), |(n, d, x), p| {
+                                (n + (p * a.powf(x)), d + a.powf(x), x + 1.0)
+                            });
+
+                        if d == 0.0 {
+                            0.0
+                        } else {
+                            n / d
+                        }
+                    }
+                }
+                Average::Maximum(days) => {
+                    if day < *days || day == 0 {
+                        0.0
+                    } else {
+                        *stock[(day - *days)..day]
+                            .iter()
+                            .max_by(|a, b| a.partial_cmp(b).unwrap())
+                            .unwrap_or(&0.0)
+                    }
+                }
+                Average::Minimum(days) => {
+                    if day < *days || *days == 0 {
+                        0.0
+                    } else {
+                        stock[(day - *days)..day].iter().copied().fold(f64::MAX, f64::min)
+                    }
+                }
+                Average::Weighted(days) => {
+                    if day < *days || *days == 0 {
+                        0.0
+                    } else {
+                        // the most recent day has the most weight
+                        let (n, d) = stock[(day - *days)..day]
+                            .iter()
+                            .enumerate()
+                            .fold((0.0, 0.0), |(n, d), (i, p)| {
+                                (n + p * (i + 1) as f64, d + (i + 1) as f64)
+                            });
+
+                        n / d
+                    }
+                }
+            }
+        }
+
+        fn buy(&self, actor: &mut Actor, day: usize, price: f64) {
+            let price = price * (1.0 + self.options.slippage);
+            let cost = price * (1.0 + self.options.fee);
+
+            if actor.capital + actor.gains < cost + self.options.commission {
+                return;
+            }
+
+            Market::fund(actor);
+            let mut shares = (actor.capital * self.leverage() - self.options.commission).max(0.0)
+                / cost;
+
+            if !self.options.fractional {
+                shares = shares.floor();
+            }
+
+            if shares <= 0.0 {
+                return;
+            }
+
+            let spent = shares * cost + self.options.commission;
+
+            if actor.stocks <= 0.0 {
+                actor.entry = price;
+                actor.peak = price;
+                actor.bought = day;
+            } else {
+                actor.entry =
+                    (actor.entry * actor.stocks + price * shares) / (actor.stocks + shares);
+            }
+
+            actor.capital -= spent;
+            actor.stocks += shares;
+            actor.trades += 1;
+            actor.record(day, true, price, shares);
+            trace!("Purchased {shares} stocks at ${price} a share to lose ${spent:.2}");
+            trace!(
+                "{} shares, ${:.2} in capital, ${:.2} in gains",
+                actor.stocks,
+                actor.capital,
+                actor.gains
+            );
+        }
+
+        // Top up the capital trades are sized by from the gains
+        fn fund(actor: &mut Actor) {
+            if actor.capital < 20000.0 {
+                if actor.gains > 20000.0 {
+                    actor.gains -= 20000.0;
+                    actor.capital += 20000.0;
+                    trace!("Borrowing $20,000 from gains");
+                } else if actor.gains > 0.0 {
+                    trace!("Borrowing ${} from gains", actor.gains);
+                    actor.capital += actor.gains;
+                    actor.gains = 0.0;
+                }
+            }
+        }
+
+        fn leverage(&self) -> f64 {
+            self.options.leverage.unwrap_or(1.0)
+        }
+
+        // Sell short as many shares as the capital allows, if nothing is held
+        fn short(&self, actor: &mut Actor, day: usize, price: f64) {
+            if actor.stocks != 0.0 || actor.capital + actor.gains <= self.options.commission {
+                return;
+            }
+
+            let price = price * (1.0 - self.options.slippage);
+            Market::fund(actor);
+            let mut shares = (actor.capital * self.leverage() - self.options.commission).max(0.0)
+                / (price * (1.0 + self.options.fee));
+
+            if !self.options.fractional {
+                shares = shares.floor();
+            }
+
+            if shares <= 0.0 {
+                return;
+            }
+
+            let earned = shares * price * (1.0 - self.options.fee) - self.options.commission;
+            actor.capital += earned;
+            actor.stocks = -shares;
+            actor.entry = price;
+            actor.peak = price;
+            actor.bought = day;
+            actor.trades += 1;
+            actor.record(day, false, price, shares);
+            trace!("Shorted {shares} shares at ${price} to gain ${earned:.2}");
+        }
+
+        // Buy back every share sold short
+        fn cover(&self, actor: &mut Actor, day: usize, price: f64) {
+            if actor.stocks >= 0.0 {
+                return;
+            }
+
+            let price = price * (1.0 + self.options.slippage);
+            let shares = -actor.stocks;
+            let spent = shares * price * (1.0 + self.options.fee) + self.options.commission;
+            actor.capital -= spent;
+            actor.stocks = 0.0;
+            actor.trades += 1;
+            actor.record(day, true, price, shares);
+            trace!("Covered {shares} shares at ${price} to lose ${spent:.2}");
+        }
+
+        // Sell or cover whatever is held
+        fn close(&self, actor: &mut Actor, day: usize, price: f64) {
+            self.sell(actor, day, price);
+            self.cover(actor, day, price);
+        }
+
+        // Buy up to `budget` of a stock with cash from the account
+        fn buy_from(&self, cash: &mut f64, actor: &mut Actor, day: usize, price: f64, budget: f64) {
+            let price = price * (1.0 + self.options.slippage);
+            let cost = price * (1.0 + self.options.fee);
+            let mut shares = (budget.min(*cash) - self.options.commission).max(0.0) / cost;
+
+            if !self.options.fractional {
+                shares = shares.floor();
+            }
+
+            if shares <= 0.0 {
+                return;
+            }
+
+            let spent = shares * cost + self.options.commission;
+            *cash -= spent;
+            actor.capital += spent;
+            actor.stocks += shares;
+            actor.entry = price;
+            actor.peak = price;
+            actor.bought = day;
+            actor.trades += 1;
+            actor.record(day, true, price, shares);
+        }
+
+        // Sell every share of a stock into the account's cash
+        fn sell_from(&self, cash: &mut f64, actor: &mut Actor, day: usize, price: f64) {
+            if actor.stocks <= 0.0 {
+                return;
+            }
+
+            let price = price * (1.0 - self.options.slippage);
+            let shares = actor.stocks;
+            let earned = shares * price * (1.0 - self.options.fee) - self.options.commission;
+            *cash += earned;
+            actor.gains += earned - actor.capital;
+            actor.capital = 0.0;
+            actor.stocks = 0.0;
+            actor.trades += 1;
+            actor.record(day, false, price, shares);
+        }
+
+        fn sell(&self, actor: &mut Actor, day: usize, price: f64) {
+            if actor.stocks <= 0.0 {
+                return;
+            }
+
+            let price = price * (1.0 - self.options.slippage);
+            let shares = actor.stocks;
+            let earned = shares * price * (1.0 - self.options.fee) - self.options.commission;
+            actor.gains += earned;
+            actor.stocks = 0.0;
+            actor.trades += 1;
+            actor.record(day, false, price, shares);
+            trace!("Sold {shares} shares at ${price} to gain ${earned:.2}");
+            trace!(
+                "{} shares, ${:.2} in capital, ${:.2} in gains",
+                actor.stocks,
+                actor.capital,
+                actor.gains
+            );
+        }
+    }
+
+    impl Market {
+        /// The profit made on each stock and the objective, if it isn't the profit,
+        /// compared with buying and holding and with a moving-average crossover
+        pub fn summary(&self, genotype: &[u8]) -> Result<String, String> {
+            Ok(format!(
+                "{} vs {} buying and holding vs {} on a 50/200 day SMA crossover",
+                self.describe(&self.outcome(&self.plans(genotype)?, false)),
+                self.describe(&self.outcome(&self.holding(), false)),
+                self.describe(&self.outcome(&self.crossing(), false))
+            ))
+        }
+
+        fn describe(&self, outcome: &Outcome) -> String {
+            let (simulations, objective) = (&outcome.stocks, self.options.objective);
+            let stocks = self
+                .histories
+                .iter()
+                .zip(simulations)
+                .map(|(h, s)| format!("{} ${:.2}", h.ticker, s.profit))
+                .collect::<Vec<String>>()
+                .join(", ");
+
+            let mut s = format!(
+                "${:.2} ({})",
+                simulations.iter().map(|s| s.profit).sum::<f64>(),
+                stocks
+            );
+
+            match self.options.aggregate {
+                Some(Aggregate::BeatHold) => {
+                    let beaten = self.beaten(simulations);
+                    let tickers = self
+                        .histories
+                        .iter()
+                        .zip(&beaten)
+                        .filter(|(_, b)| **b)
+                        .map(|(h, _)| h.ticker.as_str())
+                        .collect::<Vec<&str>>();
+
+                    s += &format!(
+                        " beating buying and holding on {} of {} by {objective} ({})",
+                        tickers.len(),
+                        beaten.len(),
+                        tickers.join(", ")
+                    );
+                }
+                Some(aggregate) => {
+                    let stocks = self
+                        .histories
+                        .iter()
+                        .zip(simulations)
+                        .map(|(h, s)| format!("{} {:.4}", h.ticker, objective.measure(s)))
+                        .collect::<Vec<String>>()
+                        .join(", ");
+
+                    s += &format!(
+                        " with a {aggregate} {objective} of {:.4} ({stocks})",
+                        self.score(outcome)
+                    );
+                }
+                None if objective != Objective::TotalReturn => {
+                    s += &format!(" with a {objective} of {:.4}", self.score(outcome));
+                }
+                None => {}
+            }
+
+            s
+        }
+
+        // The fitness of an outcome: the objective on the portfolio's account, or on
+        // each stock combined as the options say
+        fn score(&self, outcome: &Outcome) -> f64 {
+            let objective = self.options.objective;
+
+            match (&outcome.account, self.options.aggregate) {
+                (Some(account), _) => objective.score(std::slice::from_ref(account)),
+                (None, None) => objective.score(&outcome.stocks),
+                (None, Some(Aggregate::BeatHold)) => {
+                    let beaten = self.beaten(&outcome.stocks);
+                    let values: Vec<f64> =
+                        beaten.iter().map(|b| if *b { 1.0 } else { 0.0 }).collect();
+                    Aggregate::BeatHold.combine(&values)
+                }
+                (None, Some(aggregate)) => {
+                    let values: Vec<f64> =
+                        outcome.stocks.iter().map(|s| objective.measure(s)).collect();
+                    aggregate.combine(&values)
+                }
+            }
+        }
+
+        // Whether each stock did better by the objective than buying and holding it
+        fn beaten(&self, simulations: &[Simulation]) -> Vec<bool> {
+            let objective = self.options.objective;
+            let held = self.held.get_or_init(|| {
+                self.buy_and_hold().iter().map(|s| objective.measure(s)).collect()
+            });
+
+            simulations.iter().zip(held).map(|(s, h)| objective.measure(s) > *h).collect()
+        }
+
+        /// The profit made trading each stock with `genotype`
+        pub fn returns(&self, genotype: &[u8]) -> Result<Vec<f64>, String> {
+            Ok(self.simulate(genotype)?.iter().map(|s| s.profit).collect())
+        }
+
+        /// Trade each stock with `genotype`
+        pub fn simulate(&self, genotype: &[u8]) -> Result<Vec<Simulation>, String> {
+            self.trade(genotype, false)
+        }
+
+        /// Trade each stock with `genotype`, recording every trade
+        pub fn ledger(&self, genotype: &[u8]) -> Result<Vec<Simulation>, String> {
+            self.trade(genotype, true)
+        }
+
+        fn trade(&self, genotype: &[u8], record: bool) -> Result<Vec<Simulation>, String> {
+            let simulations = self.outcome(&self.plans(genotype)?, record).stocks;
+
+            for (history, simulation) in self.histories.iter().zip(&simulations) {
+                trace!(
+                    "{} Made ${:.2} on {}",
+                    genotype.iter().map(|c| *c as char).collect::<String>(),
+                    simulation.profit,
+                    history.ticker
+                );
+            }
+
+            Ok(simulations)
+        }
+
+        // What `genotype` does on each day of each history, or why it can't be traded
+        fn plans(&self, genotype: &[u8]) -> Result<Vec<Plan>, String> {
+            if self.options.rules {
+                let rule = Market::rule(genotype)?;
+
+                return Ok(self
+                    .histories
+                    .par_iter()
+                    .enumerate()
+                    .map(|(ticker, history)| {
+                        let signals = rule.signals(self, ticker);
+
+                        Market::plan(history, 0, Stops::default(), |day| {
+                            if signals[day] {
+                                Signal::Buy
+                            } else {
+                                Signal::Sell
+                            }
+                        })
+                    })
+                    .collect());
+            }
+
+            let strategy = self.strategy(genotype)?;
+            let entry = Genes::new(&strategy.entry);
+            let exit = strategy.exit.as_ref().map(Genes::new);
+            let stops = strategy.stops.unwrap_or_default();
+            let direction = strategy.direction.unwrap_or(Direction::Long);
+
+            if !entry.applicable() {
+                trace!("No applicable strategies");
+                return Ok(self
+                    .histories
+                    .iter()
+                    .map(|h| Market::plan(h, h.start, Stops::default(), |_| Signal::Hold))
+                    .collect());
+            }
+
+            Ok(self.histories.par_iter().enumerate().map(|(ticker, history)| {
+                let series = entry.series(self, ticker);
+                let exit_series = exit.map(|exit| exit.series(self, ticker));
+
+                let plan = Market::plan(history, entry.lowest(), stops, |day| {
+                    let buy = entry.holds(entry.readings(&series, history, day), day);
+                    let sell = exit.zip(exit_series.as_ref()).map(|(exit, series)| {
+                        exit.holds(exit.readings(series, history, day), day)
+                    });
+
+                    entry.signal(buy, sell, day)
+                });
+
+                Plan { direction, ..plan }
+            }).collect())
+        }
+
+        /// The indicator's value on every day of history `ticker`, computed the first
+        /// time any strategy needs it. Genes differing only in their threshold share
+        /// a series.
+        pub(crate) fn series(&self, ticker: usize, indicator: Indicator) -> Series {
+            let key = (indicator.kind(), indicator.period(), ticker);
+            self.cache.get(key, || indicator.series(&self.histories[ticker]))
+        }
+
+        // The signals for `history` from day `first` up to its last day, on which
+        // everything is sold. `signal` is called for consecutive days.
+        fn plan(
+            history: &History,
+            first: usize,
+            stops: Stops,
+            mut signal: impl FnMut(usize) -> Signal,
+        ) -> Plan {
+            let last = history.len() - 1;
+            let first = first.max(history.start).min(last);
+
+            Plan {
+                first,
+                stops,
+                direction: Direction::Long,
+                signals: (0..=last)
+                    .map(|day| {
+                        if day < first || day == last {
+                            Signal::Hold
+                        } else {
+                            signal(day)
+                        }
+                    })
+                    .collect(),
+            }
+        }
+
+        // Carry out `plans`, on each history separately or in one portfolio
+        fn outcome(&self, plans: &[Plan], record: bool) -> Outcome {
+            if self.options.portfolio {
+                return self.portfolio(plans, record);
+            }
+
+            Outcome {
+                stocks: self
+                    .histories
+                    .par_iter()
+                    .zip(plans)
+                    .map(|(history, plan)| self.replay(history, plan, record))
+                    .collect(),
+                account: None,
+            }
+        }
+
+        // The kind of byte `i` of a genotype, which ends in a direction gene after a
+        // '/' when trading short
+        fn locus(&self, i: usize) -> Locus {
+            match self.len() - i {
+                1 if self.options.short => Locus::Direction,
+                2 if self.options.short => Locus::Fixed,
+                _ => Locus::of(i),
+            }
+        }
+
+        fn rule(genotype: &[u8]) -> std::result::Result<Rule, String> {
+            Rule::parse(&genotype.iter().map(|c| *c as char).collect::<String>())
+        }
+
+        /// The strategy `genotype` encodes, if it's one this market's options evolve:
+        /// exit genes and stops only with `--exits`, and a direction only with
+        /// `--short`
+        pub fn strategy(&self, genotype: &[u8]) -> std::result::Result<Strategy, String> {
+            let strategy = Strategy::decode(genotype)?;
+
+            if strategy.exit.is_some() != self.options.exits {
+                return Err(format!(
+                    "exit genes and stops are expected {}",
+                    if self.options.exits { "with --exits" } else { "only with --exits" }
+                ));
+            }
+
+            if strategy.direction.is_some() != self.options.short {
+                return Err(format!(
+                    "a direction is expected {}",
+                    if self.options.short { "with --short" } else { "only with --short" }
+                ));
+            }
+
+            Ok(strategy)
+        }
+
+        // Trade `history` from the plan's first day on each day's signal, unless
+        // its stops close the position first, then close it on the last day. Interest
+        // is charged on what's borrowed each day, and a position is closed at the
+        // day's close when equity falls below its margin.
+        fn replay(&self, history: &History, plan: &Plan, record: bool) -> Simulation {
+            let stock = &history.close;
+            let mut actor = Actor::new(self.funds, record);
+            let year = history.year();
+            let interest = self.options.borrow_rate / year;
+
+            let first = plan.first;
+            let mut equity = vec![self.funds; first - history.start.min(first)];
+
+            for (day, close) in stock.iter().enumerate().take(stock.len() - 1).skip(first) {
+                let price = history.price(day, self.options.trade_at_open);
+                self.step(&mut actor, plan, day, price, *close, interest);
+                equity.push(actor.capital + actor.gains + actor.stocks * close);
+            }
+
+            self.close(&mut actor, stock.len() - 1, stock[stock.len() - 1]);
+            equity.push(actor.capital + actor.gains);
+
+            Simulation {
+                profit: actor.gains + actor.capital - self.funds,
+                trades: actor.trades,
+                equity,
+                ledger: actor.ledger.unwrap_or_default(),
+                year,
+            }
+        }
+
+        // Trade `day` at `price` on the plan's signal, unless the stops close the
+        // position first, then charge interest on what's borrowed and make a margin
+        // call if equity is short at the day's close
+        fn step(
+            &self,
+            actor: &mut Actor,
+            plan: &Plan,
+            day: usize,
+            price: f64,
+            close: f64,
+            interest: f64,
+        ) {
+            if actor.stocks > 0.0 {
+                actor.peak = actor.peak.max(price);
+            } else if actor.stocks < 0.0 {
+                actor.peak = actor.peak.min(price);
+            }
+
+            if actor.stocks != 0.0 && plan.stops.triggered(actor, price) {
+                self.close(actor, day, price);
+            } else {
+                self.signal(actor, plan, day, price);
+            }
+
+            let cash = actor.capital + actor.gains;
+            let borrowed = (-actor.stocks).max(0.0) * close + (-cash).max(0.0);
+
+            if borrowed > 0.0 {
+                actor.capital -= borrowed * interest;
+            }
+
+            let value = actor.capital + actor.gains + actor.stocks * close;
+
+            if value < self.options.margin * (actor.stocks * close).abs() {
+                trace!("Margin call at ${close} with ${value:.2} in equity");
+                self.close(actor, day, close);
+            }
+        }
+
+        // Act on the plan's signal for `day`. Positions are only closed by a signal
+        // once they've been held for the plan's minimum holding period, and closing
+        // one can open one the other way.
+        fn signal(&self, actor: &mut Actor, plan: &Plan, day: usize, price: f64) {
+            let held = day >= actor.bought + plan.stops.hold;
+
+            match (plan.signals[day], plan.direction) {
+                (Signal::Buy, direction) if actor.stocks < 0.0 && held => {
+                    self.cover(actor, day, price);
+
+                    if direction == Direction::Both {
+                        self.buy(actor, day, price);
+                    }
+                }
+                (Signal::Sell, direction) if actor.stocks > 0.0 && held => {
+                    self.sell(actor, day, price);
+
+                    if direction == Direction::Both {
+                        self.short(actor, day, price);
+                    }
+                }
+                (Signal::Buy, Direction::Long | Direction::Both) if actor.stocks >= 0.0 => {
+                    self.buy(actor, day, price)
+                }
+                (Signal::Sell, Direction::Short | Direction::Both) if actor.stocks <= 0.0 => {
+                    self.short(actor, day, price)
+                }
+                _ => {}
+            }
+        }
+
+        // Trade every history from one account on a shared calendar. Each day's
+        // sells come before its buys so their cash can be spent, and positions are
+        // only opened when there's none.
+        fn portfolio(&self, plans: &[Plan], record: bool) -> Outcome {
+            let (_, calendar) = self.calendar();
+            let count = self.histories.len();
+
+            let mut cash = self.funds;
+            // each actor's gains are the profit made on its stock, and its capital
+            // what's been spent on the shares it holds
+            let mut actors: Vec<Actor> = (0..count).map(|_| Actor::new(0.0, record)).collect();
+            let mut closes = vec![0.0; count];
+            let mut stocks: Vec<Vec<f64>> = self
+                .histories
+                .iter()
+                .map(|h| Vec::with_capacity(h.len() - h.start))
+                .collect();
+            let mut equity = Vec::with_capacity(calendar.len());
+            let mut stopped = vec![false; count];
+
+            for bars in &calendar {
+                let value =
+                    cash + actors.iter().zip(&closes).map(|(a, c)| a.stocks * c).sum::<f64>();
+
+                for &(i, day) in bars {
+                    let (history, plan, actor) = (&self.histories[i], &plans[i], &mut actors[i]);
+                    let last = history.len() - 1;
+                    stopped[i] = false;
+
+                    if day == last {
+                        self.sell_from(&mut cash, actor, day, history.close[last]);
+                    } else if day >= plan.first {
+                        let price = history.price(day, self.options.trade_at_open);
+
+                        if actor.stocks > 0.0 {
+                            actor.peak = actor.peak.max(price);
+                        }
+
+                        if actor.stocks > 0.0 && plan.stops.triggered(actor, price) {
+                            self.sell_from(&mut cash, actor, day, price);
+                            stopped[i] = true;
+                        } else if matches!(plan.signals[day], Signal::Sell)
+                            && day >= actor.bought + plan.stops.hold
+                        {
+                            self.sell_from(&mut cash, actor, day, price);
+                        }
+                    }
+                }
+
+                let weights = self.weights(bars);
+
+                for (&(i, day), weight) in bars.iter().zip(weights) {
+                    let (history, actor) = (&self.histories[i], &mut actors[i]);
+
+                    if !stopped[i]
+                        && actor.stocks <= 0.0
+                        && matches!(plans[i].signals[day], Signal::Buy)
+                    {
+                        let price = history.price(day, self.options.trade_at_open);
+                        self.buy_from(&mut cash, actor, day, price, value * weight);
+                    }
+                }
+
+                for &(i, day) in bars {
+                    let (close, actor) = (self.histories[i].close[day], &actors[i]);
+                    closes[i] = close;
+                    stocks[i].push(actor.gains + actor.stocks * close - actor.capital);
+                }
+
+                equity.push(
+                    cash + actors.iter().zip(&closes).map(|(a, c)| a.stocks * c).sum::<f64>(),
+                );
+            }
+
+            Outcome {
+                // the shared calendar has as many bars as the history with the most
+                account: Some(Simulation {
+                    profit: cash - self.funds,
+                    trades: actors.iter().map(|a| a.trades).sum(),
+                    equity,
+                    ledger: Vec::new(),
+                    year: self.histories.iter().map(History::year).fold(TRADING_DAYS, f64::max),
+                }),
+                stocks: actors
+                    .into_iter()
+                    .zip(stocks)
+                    .zip(&self.histories)
+                    .map(|((actor, equity), history)| Simulation {
+                        profit: actor.gains,
+                        trades: actor.trades,
+                        equity,
+                        ledger: actor.ledger.unwrap_or_default(),
+                        year: history.year(),
+                    })
+                    .collect(),
+            }
+        }
+
+        // The fraction of the portfolio's value to put into each of the day's stocks
+        fn weights(&self, bars: &[(usize, usize)]) -> Vec<f64> {
+            let count = self.histories.len() as f64;
+
+            match self.options.sizing {
+                Sizing::Equal => vec![1.0 / count; bars.len()],
+                Sizing::Fraction => vec![self.options.fraction; bars.len()],
+                Sizing::Volatility => {
+                    let inverse: Vec<Option<f64>> = bars
+                        .iter()
+                        .map(|&(i, day)| {
+                            self.histories[i].volatility(day).filter(|v| *v > 0.0).map(|v| 1.0 / v)
+                        })
+                        .collect();
+                    let total = inverse.iter().flatten().sum::<f64>();
+                    let known = inverse.iter().flatten().count() as f64;
+
+                    // stocks without enough history get an equal share, and the
+                    // rest share what's left by how volatile they are
+                    inverse
+                        .iter()
+                        .map(|v| match v {
+                            Some(v) => v / total * known / count,
+                            None => 1.0 / count,
+                        })
+                        .collect()
+                }
+            }
+        }
+
+        /// The time of each bar any history trades on, and which day of which
+        /// history falls on it. Histories without dates are aligned from their first
+        /// trading day.
+        pub fn calendar(&self) -> (Vec<Option<NaiveDateTime>>, Bars) {
+            if self.histories.iter().all(|h| h.times.len() == h.len()) {
+                let mut days = std::collections::BTreeMap::new();
+
+                for (i, history) in self.histories.iter().enumerate() {
+                    for day in history.start..history.len() {
+                        days.entry(history.times[day]).or_insert_with(Vec::new).push((i, day));
+                    }
+                }
+
+                return days.into_iter().map(|(time, bars)| (Some(time), bars)).unzip();
+            }
+
+            let length = self.histories.iter().map(|h| h.len() - h.start).max().unwrap_or(0);
+
+            (0..length)
+                .map(|k| {
+                    let bars = self
+                        .histories
+                        .iter()
+                        .enumerate()
+                        .filter(|(_, h)| h.start + k < h.len())
+                        .map(|(i, h)| (i, h.start + k))
+                        .collect();
+                    (None, bars)
+                })
+                .unzip()
+        }
+
+        /// This market and each held-out one, by name
+        pub fn samples(&self) -> Vec<(&str, &Market)> {
+            std::iter::once(("in-sample", self))
+                .chain(self.held_out.iter().map(|(name, m)| (name.as_str(), m)))
+                .collect()
+        }
+
+        /// Write the trades each genotype makes on its sample to `file` as CSV, and the
+        /// daily equity to the same name ending in -equity
+        pub fn write_trades(file: &str, samples: &[(&str, &Market, &[u8])]) -> Result<(), String> {
+            let mut trades =
+                String::from("sample,ticker,day,date,action,price,shares,capital,gains\n");
+            let mut equity = String::from("sample,ticker,day,date,equity\n");
+
+            for (sample, market, genotype) in samples {
+                let outcome = market.outcome(&market.plans(genotype)?, true);
+
+                for (history, simulation) in market.histories.iter().zip(outcome.stocks) {
+                    let date = |day: usize| {
+                        history.times.get(day).map_or(String::new(), stamp)
+                    };
+
+                    for t in &simulation.ledger {
+                        trades += &format!(
+                            "{sample},{},{},{},{},{},{},{},{}\n",
+                            history.ticker,
+                            t.day,
+                            date(t.day),
+                            if t.buy { "buy" } else { "sell" },
+                            t.price,
+                            t.shares,
+                            t.capital,
+                            t.gains
+                        );
+                    }
+
+                    for (i, e) in simulation.equity.iter().enumerate() {
+                        let day = history.start + i;
+                        equity += &format!("{sample},{},{day},{},{e}\n", history.ticker, date(day));
+                    }
+                }
+
+                // each stock's equity is the profit made on it, so add the account's
+                if let Some(account) = outcome.account {
+                    let (dates, _) = market.calendar();
+
+                    for (day, (e, date)) in account.equity.iter().zip(dates).enumerate() {
+                        let date = date.as_ref().map_or(String::new(), stamp);
+                        equity += &format!("{sample},portfolio,{day},{date},{e}\n");
+                    }
+                }
+            }
+
+            let path = Path::new(file);
+            let equity_file = path.with_file_name(format!(
+                "{}-equity{}",
+                path.file_stem().map_or(file.into(), |s| s.to_string_lossy()),
+                path.extension()
+                    .map_or(String::new(), |e| format!(".{}", e.to_string_lossy()))
+            ));
+
+            for (file, contents) in [(path, trades), (equity_file.as_path(), equity)] {
+                if let Err(e) = std::fs::write(file, contents) {
+                    panic!("Error writing {}: {}", file.display(), e);
+                }
+            }
+
+            Ok(())
+        }
+
+        /// What `genotype` makes on `samples` resamplings of its results, each drawn
+        /// with its own generator seeded from `seed`, so they're the same on any
+        /// number of threads
+        pub fn bootstrap(
+            &self,
+            genotype: &[u8],
+            samples: usize,
+            seed: u64,
+        ) -> Result<Bootstrap, String> {
+            // the equity of each stock, or of the portfolio
+            let series = |outcome: Outcome| match outcome.account {
+                Some(account) => vec![account],
+                None => outcome.stocks,
+            };
+            let (strategy, held) = match self.options.resampling {
+                Resampling::Blocks => (
+                    series(self.outcome(&self.plans(genotype)?, false)),
+                    series(self.outcome(&self.holding(), false)),
+                ),
+                Resampling::Starts => (Vec::new(), Vec::new()),
+            };
+
+            let draw = |sample: usize| -> Result<(f64, f64, bool), String> {
+                let mut rng = StdRng::seed_from_u64(seed.wrapping_add(sample as u64));
+
+                match self.options.resampling {
+                    Resampling::Blocks => Ok(self.draw_blocks(&strategy, &held, &mut rng)),
+                    Resampling::Starts => {
+                        let histories = self
+                            .histories
+                            .iter()
+                            .map(|h| {
+                                let first = rng.gen_range(h.start..=(h.start + h.len() - 1) / 2);
+                                h.segment(first, h.len())
+                            })
+                            .collect();
+                        let market = self.with(histories);
+                        let outcome = market.outcome(&market.plans(genotype)?, false);
+                        let held = market.outcome(&market.holding(), false);
+
+                        Ok((profit(&outcome), drawdown(&outcome), profit(&outcome) > profit(&held)))
+                    }
+                }
+            };
+
+            let draws = (0..samples).into_par_iter().map(draw).collect::<Result<Vec<_>, _>>()?;
+            let (returns, rest): (Vec<f64>, Vec<(f64, bool)>) =
+                draws.into_iter().map(|(r, d, b)| (r, (d, b))).unzip();
+            let (drawdowns, beaten) = rest.into_iter().unzip();
+
+            Ok(Bootstrap {
+                returns,
+                drawdowns,
+                beaten,
+            })
+        }
+
+        // A block bootstrap of the daily returns of each stock, or of the portfolio,
+        // drawing the same days of buying and holding so the two can be compared
+        fn draw_blocks(
+            &self,
+            strategy: &[Simulation],
+            held: &[Simulation],
+            rng: &mut StdRng,
+        ) -> (f64, f64, bool) {
+            let (mut made, mut drawdowns, mut benchmark) = (0.0, 0.0, 0.0);
+
+            for (strategy, held) in strategy.iter().zip(held) {
+                let (returns, held_returns) = (strategy.daily_returns(), held.daily_returns());
+                let block = self.options.block.clamp(1, returns.len().max(1));
+                let mut days = Vec::with_capacity(returns.len());
+
+                while days.len() < returns.len() {
+                    let from = rng.gen_range(0..=returns.len() - block);
+                    days.extend(from..from + block);
+                }
+                days.truncate(returns.len());
+
+                let path = |first: f64, returns: &[f64]| {
+                    let equity = std::iter::once(first)
+                        .chain(days.iter().scan(first, |e, day| {
+                            *e *= 1.0 + returns[*day];
+                            Some(*e)
+                        }))
+                        .collect::<Vec<f64>>();
+
+                    Simulation {
+                        profit: equity[equity.len() - 1] - first,
+                        trades: 0,
+                        equity,
+                        ledger: Vec::new(),
+                        year: strategy.year,
+                    }
+                };
+
+                let sample = path(strategy.equity[0], &returns);
+                made += sample.profit;
+                drawdowns += sample.max_drawdown();
+                benchmark += path(held.equity[0], &held_returns).profit;
+            }
+
+            (made, drawdowns / strategy.len().max(1) as f64, made > benchmark)
+        }
+
+        /// Buying on the first day and selling on the last, as a benchmark
+        pub fn buy_and_hold(&self) -> Vec<Simulation> {
+            self.outcome(&self.holding(), false).stocks
+        }
+
+        fn holding(&self) -> Vec<Plan> {
+            self.histories
+                .iter()
+                .map(|history| {
+                    let first = history.start;
+                    Market::plan(history, first, Stops::default(), |day| {
+                        if day == first {
+                            Signal::Buy
+                        } else {
+                            Signal::Hold
+                        }
+                    })
+                })
+                .collect()
+        }
+
+        /// Holding while the 50 day simple moving average is above the 200 day
+        /// one, as a benchmark
+        pub fn sma_crossover(&self) -> Vec<Simulation> {
+            self.outcome(&self.crossing(), false).stocks
+        }
+
+        fn crossing(&self) -> Vec<Plan> {
+            let (short, long) = (Average::Simple(50), Average::Simple(200));
+
+            self.histories
+                .iter()
+                .map(|history| {
+                    let stock = &history.close;
+
+                    Market::plan(history, 200, Stops::default(), |day| {
+                        let short = Market::get_average(stock, day, &short);
+                        let long = Market::get_average(stock, day, &long);
+
+                        if short > long {
+                            Signal::Buy
+                        } else if short < long {
+                            Signal::Sell
+                        } else {
+                            Signal::Hold
+                        }
+                    })
+                })
+                .collect()
+        }
+    }
+
+    impl Problem for Market {
+        fn fitness(&self, genotype: &[u8]) -> Fitness {
+            debug!("Evaluating {}", genotype.iter().map(|x| *x as char).collect::<String>());
+
+            let plans = match self.plans(genotype) {
+                Ok(plans) => plans,
+                Err(e) => {
+                    warn!("{} is invalid: {e}", String::from_utf8_lossy(genotype));
+                    return Fitness::Invalid;
+                }
+            };
+            let penalty = if self.options.rules {
+                let size = Market::rule(genotype).map_or(0, |rule| rule.size());
+                self.options.parsimony * size as f64
+            } else {
+                0.0
+            };
+
+            let outcome = self.outcome(&plans, false);
+            let funds = outcome.stocks.iter().map(|s| s.profit).sum::<f64>();
+
+            let avg = funds / self.histories.len() as f64;
+            debug!("Average return: ${:.2}", avg);
+            debug!("Total return: ${:.2}", funds);
+
+            Fitness::Valid(self.score(&outcome) - penalty)
+        }
+
+        fn mutate(&self, mutation_rate: f64, force_mutation: bool, g: &mut Genotype) {
+            let mut rng = rand::thread_rng();
+            let methods = self.methods();
             let operators = [b'&', b'|'];
 
-            let mut mutated = false;
+            if self.options.rules {
+                if let Ok(mut rule) = Market::rule(&g.genotype) {
+                    if force_mutation || rng.gen_bool(mutation_rate) {
+                        let old = rule.clone();
+
+                        // a new subtree can happen to match the old one
+                        while rule == old {
+                            rule.mutate(self.options.max_depth, &methods, &mut rng);
+
+                            if !force_mutation {
+                                break;
+                            }
+                        }
+
+                        g.genotype = rule.to_string().into_bytes();
+                    }
+                }
+
+                g.fitness = self.fitness(&g.genotype);
+                return;
+            }
 
             for i in 0..g.len() {
                 if rng.gen_bool(mutation_rate) {
-                    mutated = true;
-
-                    match i {
-                        0 | 5 | 10 => {
+                    match self.locus(i) {
+                        Locus::Kind => {
                             if force_mutation {
-                                let new = rng.gen_range(0..2);
+                                let new = rng.gen_range(0..methods.len() - 1);
 
                                 if g.genotype[i] == methods[new] {
                                     g.genotype[i] = methods[new + 1];
@@ -577,10 +3250,10 @@ pub mod stocks {
                                     g.genotype[i] = methods[new];
                                 }
                             } else {
-                                g.genotype[i] = methods[rng.gen_range(0..=2)];
+                                g.genotype[i] = methods[rng.gen_range(0..methods.len())];
                             }
                         }
-                        1..=3 | 6..=8 | 11..=13 => {
+                        Locus::Digit => {
                             if force_mutation {
                                 let new = b'0' + rng.gen_range(0..9) as u8;
 
@@ -593,7 +3266,7 @@ pub mod stocks {
                                 g.genotype[i] = b'0' + rng.gen_range(0..=9) as u8;
                             }
                         }
-                        4 | 9 => {
+                        Locus::Operator => {
                             if force_mutation {
                                 if g.genotype[i] == b'&' {
                                     g.genotype[i] = b'|';
@@ -604,16 +3277,20 @@ pub mod stocks {
                                 g.genotype[i] = operators[rng.gen_range(0..=1)];
                             }
                         }
-                        _ => {
-                            panic!("Invalid genotype!");
+                        Locus::Direction => {
+                            let directions = DIRECTIONS.iter().filter(|d| {
+                                !force_mutation || **d != g.genotype[i]
+                            });
+                            g.genotype[i] = *directions.choose(&mut rng).unwrap();
                         }
+                        // separators and the letters of stops
+                        Locus::Fixed => {}
                     }
                 }
             }
 
-            if mutated {
-                g.fitness = self.fitness(&g.genotype);
-            }
+            // crossover may have changed the genotype even if nothing mutated
+            g.fitness = self.fitness(&g.genotype);
         }
 
         fn generate_genotype(&self, force_create: bool) -> Genotype {
@@ -623,32 +3300,25 @@ pub mod stocks {
                 fitness: Fitness::Invalid,
             };
 
-            let methods = [b's', b'e', b'm'];
-            let operators = [b'&', b'|'];
+            let methods = self.methods();
 
-            for i in 0..self.len() {
-                match i {
-                    0 | 5 | 10 => {
-                        g.genotype.push(methods[rng.gen_range(0..=2)]);
-                    },
-                    1 | 2 | 6 | 7 | 11 | 12 => {
-                        g.genotype.push(b'0' + rng.gen_range(0..2) as u8);
-                    },
-                    2..=3 | 7..=8 | 12..=13 => {
-                        g.genotype.push(b'0' + rng.gen_range(0..=9) as u8);
-                    },
-                    /*
-                    1..=3 | 6..=8 | 11..=13 => {
-                        g.genotype.push(b'0' + rng.gen_range(0..=9) as u8);
-                    },
-                */
-                    4 | 9 => {
-                        g.genotype.push(operators[rng.gen_range(0..=1)]);
-                    },
-                    _ => {
-                        panic!("Invalid genotype!");
-                    },
-                }
+            if self.options.rules {
+                let rule = Rule::random(self.options.max_depth, &methods, &mut rng);
+                g.genotype = rule.to_string().into_bytes();
+            } else {
+                g.genotype.extend(Market::generate_genes(&methods, &mut rng));
+            }
+
+            if self.options.exits {
+                g.genotype.push(b'/');
+                g.genotype.extend(Market::generate_genes(&methods, &mut rng));
+                g.genotype.push(b'/');
+                g.genotype.extend(Stops::generate(&mut rng));
+            }
+
+            if self.options.short {
+                g.genotype.push(b'/');
+                g.genotype.push(*DIRECTIONS.choose(&mut rng).unwrap());
             }
 
             trace!(
@@ -671,78 +3341,286 @@ pub mod stocks {
         }
 
         fn format(&self, g: &Genotype) -> String {
-            if let Fitness::Valid(f) = self.fitness(&g.genotype) {
-                return format!(
-                    "{} made ${:.2}",
-                    g.genotype.iter().map(|c| *c as char).collect::<String>(),
-                    f
-                );
-            } else {
-                panic!("Invalid fitness!");
-            }
+            let text = g.genotype.iter().map(|c| *c as char).collect::<String>();
+            let results = || -> Result<String, String> {
+                let mut s = format!("{text} made {}", self.summary(&g.genotype)?);
+
+                if !self.held_out.is_empty() {
+                    s += " in sample";
+                }
+
+                // out-of-sample results side by side with the in-sample ones
+                for (name, market) in &self.held_out {
+                    s += &format!(" | {} on {name}", market.summary(&g.genotype)?);
+                }
+
+                Ok(s)
+            };
+
+            results().unwrap_or_else(|e| format!("{text} is invalid: {e}"))
         }
 
-        fn alphabet(&self) -> &Vec<u8> {
+        fn alphabet(&self) -> &[u8] {
             &self.alphabet
         }
 
         fn len(&self) -> usize {
-            14
+            let direction = if self.options.short { 2 } else { 0 };
+
+            if self.options.rules {
+                0
+            } else if self.options.exits {
+                46 + direction
+            } else {
+                14 + direction
+            }
+        }
+
+        fn variable_length(&self) -> bool {
+            self.options.rules
+        }
+
+        fn crossover(&self, a: &mut Genotype, b: &mut Genotype) {
+            if let (Ok(mut x), Ok(mut y)) = (Market::rule(&a.genotype), Market::rule(&b.genotype)) {
+                Rule::crossover(&mut x, &mut y, self.options.max_depth, &mut rand::thread_rng());
+                a.genotype = x.to_string().into_bytes();
+                b.genotype = y.to_string().into_bytes();
+            }
         }
 
         fn new(files: Vec<String>) -> Option<Self>
         where
             Self: Sized,
         {
-            let mut histories = Vec::<Vec<f64>>::with_capacity(files.len());
+            Some(Market::with_options(files, Options::default()))
+        }
+    }
 
-            for (i, file) in files.iter().enumerate() {
-                let file = match File::open(file) {
-                    Ok(f) => {
-                        histories.push(Vec::with_capacity(250));
-                        f
-                    }
-                    Err(e) => {
-                        panic!("Error reading {}: {}", file, e);
-                    }
-                };
+    impl Paper {
+        /// Paper trades `genotype` on the bars of `ticker` with the options, charging
+        /// interest for `year` bars a year
+        pub fn new(
+            options: Options,
+            ticker: &str,
+            genotype: &[u8],
+            year: f64,
+        ) -> std::result::Result<Paper, String> {
+            if options.rules {
+                return Err("Paper trading needs a genotype of genes, not --rules".to_string());
+            }
 
-                let reader = BufReader::new(file);
+            if options.portfolio {
+                return Err("Paper trading trades one stock, not a --portfolio".to_string());
+            }
 
-                for line in reader.lines() {
-                    if let Ok(l) = line {
-                        if let Ok(price) = l.parse::<f64>() {
-                            histories[i].push(price);
-                        }
+            let options = Options {
+                split: Vec::new(),
+                test_file: Vec::new(),
+                ..options
+            };
+            let interest = options.borrow_rate / year;
+            let mut market = Market::with_options(Vec::new(), options);
+            market.histories.push(History {
+                ticker: ticker.to_string(),
+                ..Default::default()
+            });
+
+            let strategy = market.strategy(genotype).map_err(|e| {
+                format!("{} is invalid: {e}", String::from_utf8_lossy(genotype))
+            })?;
+            let entry = Genes::new(&strategy.entry);
+            let exit = strategy.exit.as_ref().map(Genes::new);
+
+            // genes start every five bytes, and exit genes after the entry ones
+            let mut genes: Vec<(String, Reader)> = Vec::new();
+            let conditions = [Some(&strategy.entry), strategy.exit.as_ref()];
+
+            for (part, condition) in conditions.into_iter().enumerate() {
+                if let Some(condition) = condition {
+                    for (i, indicator) in condition.indicators.into_iter().enumerate() {
+                        let from = part * 15 + i * 5;
+                        let gene = String::from_utf8_lossy(&genotype[from..from + 4]);
+                        let name = match part {
+                            0 => gene.into_owned(),
+                            _ => format!("exit {gene}"),
+                        };
+                        genes.push((name, Reader::new(indicator)));
                     }
                 }
             }
 
-            Some(Market {
-                alphabet: vec![
-                    b'&', b'|', b's', b'e', b'm', b'0', b'1', b'2', b'3', b'4', b'5', b'6', b'7',
-                    b'8', b'9',
-                ],
-                funds: 20000.0,
-                histories,
+            Ok(Paper {
+                entry,
+                exit,
+                genes,
+                plan: Plan {
+                    first: entry.lowest(),
+                    stops: strategy.stops.unwrap_or_default(),
+                    direction: strategy.direction.unwrap_or(Direction::Long),
+                    signals: Vec::new(),
+                },
+                actor: Actor::new(market.funds, true),
+                interest,
+                closed: false,
+                market,
+            })
+        }
+
+        /// Reads the genes on the next bar and trades on what they say. Everything is
+        /// sold at the close of the `last` bar, as a backtest does. Bars that can't be
+        /// traded are refused without changing anything.
+        pub fn push(&mut self, bar: Bar, last: bool) -> Result<Tick, String> {
+            if self.closed {
+                return Err("Paper trading has already closed on the last bar".to_string());
+            }
+
+            let trade_at_open = self.market.options.trade_at_open;
+
+            if trade_at_open && bar.open.is_none() {
+                return Err("Trading at the open needs the open of every bar".to_string());
+            }
+
+            self.market.histories[0].push(&bar);
+
+            let history = &self.market.histories[0];
+            let day = history.len() - 1;
+            let trades = self.actor.ledger.as_ref().map_or(0, Vec::len);
+
+            // the last bar's signal is carried out at this bar's open
+            if trade_at_open && day > self.plan.first {
+                let price = history.price(day - 1, true);
+                let close = history.close[day - 1];
+                self.market.step(&mut self.actor, &self.plan, day - 1, price, close, self.interest);
+            }
+
+            let readings: Vec<Reading> =
+                self.genes.iter_mut().map(|(_, reader)| reader.read(history, day)).collect();
+            let genes = |from: usize| (readings[from], readings[from + 1], readings[from + 2]);
+
+            let signal = if last || day < self.plan.first || !self.entry.applicable() {
+                Signal::Hold
+            } else {
+                let buy = self.entry.holds(genes(0), day);
+                let sell = self.exit.map(|exit| exit.holds(genes(3), day));
+                self.entry.signal(buy, sell, day)
+            };
+            self.plan.signals.push(signal);
+
+            if last {
+                self.market.close(&mut self.actor, day, bar.close);
+                self.closed = true;
+            } else if !trade_at_open && day >= self.plan.first {
+                let close = bar.close;
+                self.market.step(&mut self.actor, &self.plan, day, close, close, self.interest);
+            }
+
+            let ledger = self.actor.ledger.as_deref().unwrap_or_default();
+
+            Ok(Tick {
+                day,
+                time: bar.time,
+                close: bar.close,
+                signal,
+                genes: self
+                    .genes
+                    .iter()
+                    .zip(&readings)
+                    .map(|((name, _), reading)| (name.clone(), reading.value, reading.bullish))
+                    .collect(),
+                trades: ledger[trades..].to_vec(),
+                equity: self.equity(),
             })
         }
+
+        /// Capital, gains and the value of the shares held at the last close
+        pub fn equity(&self) -> f64 {
+            let close = self.market.histories[0].close.last().copied().unwrap_or(0.0);
+            self.actor.capital + self.actor.gains + self.actor.stocks * close
+        }
+
+        /// What's been made so far, valuing the shares held at the last close
+        pub fn profit(&self) -> f64 {
+            self.equity() - self.market.funds
+        }
+
+        /// Every trade made so far
+        pub fn trades(&self) -> &[Trade] {
+            self.actor.ledger.as_deref().unwrap_or_default()
+        }
+    }
+
+    impl std::str::FromStr for Bar {
+        type Err = String;
+
+        // A closing price, or a date or time and a closing price, optionally followed
+        // by the volume, separated by commas
+        fn from_str(s: &str) -> Result<Bar, String> {
+            let cells: Vec<&str> = s.split(',').map(str::trim).collect();
+            let number = |cell: &str| {
+                cell.parse::<f64>().map_err(|_| format!("Invalid bar {s}: {cell} isn't a number"))
+            };
+
+            let (time, close, volume) = match cells[..] {
+                [close] => (None, close, None),
+                [time, close] => (Some(time), close, None),
+                [time, close, volume] => (Some(time), close, Some(number(volume)?)),
+                _ => {
+                    return Err(format!(
+                        "Invalid bar {s}, expected a closing price, or a date, a closing price \
+                         and optionally the volume"
+                    ))
+                }
+            };
+
+            let time = time
+                .map(|t| parse_time(t).ok_or_else(|| format!("Invalid bar {s}: {t} isn't a date")))
+                .transpose()?;
+
+            Ok(Bar {
+                time,
+                open: None,
+                close: number(close)?,
+                volume,
+            })
+        }
+    }
+
+    impl Display for Signal {
+        fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+            match self {
+                Signal::Buy => write!(f, "BUY"),
+                Signal::Sell => write!(f, "SELL"),
+                Signal::Hold => write!(f, "HOLD"),
+            }
+        }
     }
-}
 
-/*
-#[cfg(test)]
-mod tests {
-    use crate::{genetic::Genotype, problems::Problem, stocks::Market, Fitness};
+    impl Display for Tick {
+        fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+            match &self.time {
+                Some(time) => write!(f, "{}", stamp(time))?,
+                None => write!(f, "Bar {}", self.day)?,
+            }
 
-    #[test]
-    fn test_market_simple_moving_average() {
-        let mut market =
-            Market::new(vec!["testdata/tests/one-year-sinusoidal.txt".to_string()]).unwrap();
-        let genotype: Vec<u8> = String::from("s000&s000|s000").into();
-        let fitness = Market::fitness(&market, &genotype);
+            let genes: Vec<String> = self
+                .genes
+                .iter()
+                .map(|(name, value, bullish)| match (value, bullish) {
+                    (Some(v), true) => format!("{name} {v:.2} bullish"),
+                    (Some(v), false) => format!("{name} {v:.2} bearish"),
+                    (None, _) => format!("{name} none"),
+                })
+                .collect();
+
+            write!(f, " at {:.2}: {} ({})", self.close, self.signal, genes.join(", "))?;
+
+            for trade in &self.trades {
+                let shares = (trade.shares * 1e4).round() / 1e4;
+                let action = if trade.buy { "bought" } else { "sold" };
+                write!(f, ", {action} {shares} shares at ${:.2}", trade.price)?;
+            }
 
-        //assert!(fitness == Fitness::Valid(
+            write!(f, ", equity ${:.2}", self.equity)
+        }
     }
 }
-*/
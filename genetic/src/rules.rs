@@ -0,0 +1,411 @@
+//! Trading rules as expression trees, for evolving strategies by genetic programming.
+//!
+//! A rule compares prices and indicators and combines the comparisons, e.g.
+//! `(close > sma(50) & !(rsi(14) > 70))`. Rules are kept in genotypes as that text.
+
+use crate::problems::stocks::{Average, Indicator, Market};
+use rand::Rng;
+use std::fmt::{Display, Formatter, Result};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Rule {
+    And(Box<Rule>, Box<Rule>),
+    Or(Box<Rule>, Box<Rule>),
+    Not(Box<Rule>),
+    Above(Value, Value),
+    Below(Value, Value),
+}
+
+/// What a rule compares
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    /// The day's closing price
+    Close,
+    Indicator(Indicator),
+    Constant(f64),
+}
+
+impl Display for Rule {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        match self {
+            Rule::And(a, b) => write!(f, "({a} & {b})"),
+            Rule::Or(a, b) => write!(f, "({a} | {b})"),
+            Rule::Not(r) => match **r {
+                Rule::Above(..) | Rule::Below(..) => write!(f, "!({r})"),
+                _ => write!(f, "!{r}"),
+            },
+            Rule::Above(a, b) => write!(f, "{a} > {b}"),
+            Rule::Below(a, b) => write!(f, "{a} < {b}"),
+        }
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        match self {
+            Value::Close => write!(f, "close"),
+            Value::Indicator(indicator) => {
+                write!(f, "{}({})", Value::name(indicator), indicator.period())
+            }
+            Value::Constant(c) => write!(f, "{c}"),
+        }
+    }
+}
+
+impl Value {
+    fn name(indicator: &Indicator) -> &'static str {
+        match indicator {
+            Indicator::Average(Average::Simple(_)) => "sma",
+            Indicator::Average(Average::Exponential(_)) => "ema",
+            Indicator::Average(Average::Weighted(_)) => "wma",
+            Indicator::Average(Average::Maximum(_)) => "high",
+            Indicator::Average(Average::Minimum(_)) => "low",
+            Indicator::Rsi { .. } => "rsi",
+            Indicator::Macd(_) => "macd",
+            Indicator::Bollinger { .. } => "boll",
+            Indicator::RateOfChange { .. } => "roc",
+            Indicator::Volume { .. } => "vol",
+        }
+    }
+
+    // The indicator called `name` over `days`. Thresholds only matter to genes,
+    // so they're left at zero.
+    fn indicator(name: &str, days: usize) -> Option<Indicator> {
+        Some(match name {
+            "sma" => Indicator::Average(Average::Simple(days)),
+            "ema" => Indicator::Average(Average::Exponential(days)),
+            "wma" => Indicator::Average(Average::Weighted(days)),
+            "high" => Indicator::Average(Average::Maximum(days)),
+            "low" => Indicator::Average(Average::Minimum(days)),
+            "rsi" => Indicator::Rsi { days, level: 0.0 },
+            "macd" => Indicator::Macd(days),
+            "boll" => Indicator::Bollinger {
+                days,
+                deviations: 0.0,
+            },
+            "roc" => Indicator::RateOfChange { days, percent: 0.0 },
+            "vol" => Indicator::Volume { days, ratio: 0.0 },
+            _ => return None,
+        })
+    }
+
+    // A random comparison between values on the same scale: prices and averages
+    // with each other, and the other indicators with a threshold
+    fn comparison(methods: &[u8], rng: &mut impl Rng) -> Rule {
+        let (indicator, threshold) = Value::random(methods, rng);
+
+        let other = match indicator {
+            Indicator::Average(_) if rng.gen_bool(0.5) => Value::Close,
+            Indicator::Average(_) => {
+                let averages: Vec<u8> = b"sewmn"
+                    .iter()
+                    .copied()
+                    .filter(|m| methods.contains(m))
+                    .collect();
+                Value::Indicator(Value::random(&averages, rng).0)
+            }
+            _ => Value::Constant(threshold),
+        };
+
+        if rng.gen_bool(0.5) {
+            Rule::Above(Value::Indicator(indicator), other)
+        } else {
+            Rule::Below(Value::Indicator(indicator), other)
+        }
+    }
+
+    // An indicator of one of the kinds in `methods`, with the days a gene of
+    // that kind would have. Its threshold is returned with it.
+    fn random(methods: &[u8], rng: &mut impl Rng) -> (Indicator, f64) {
+        loop {
+            let kind = methods[rng.gen_range(0..methods.len())];
+            let gene = Market::parse(Market::generate_gene(kind, rng)).expect("Invalid gene!");
+            let threshold = match gene {
+                Indicator::Rsi { level, .. } => level,
+                Indicator::Bollinger { deviations, .. } => deviations,
+                Indicator::RateOfChange { percent, .. } => percent,
+                Indicator::Volume { ratio, .. } => ratio,
+                Indicator::Average(_) | Indicator::Macd(_) => 0.0,
+            };
+
+            if gene.period() > 0 {
+                let indicator = Value::indicator(Value::name(&gene), gene.period());
+                return (indicator.expect("Every indicator has a name"), threshold);
+            }
+        }
+    }
+}
+
+impl Rule {
+    /// Parses the text a rule is displayed as
+    pub fn parse(text: &str) -> std::result::Result<Rule, String> {
+        let mut parser = Parser { text, at: 0 };
+        let rule = parser.rule()?;
+
+        parser.skip_spaces();
+        if parser.at < text.len() {
+            return Err(format!("Unexpected '{}' in rule {text}", &text[parser.at..]));
+        }
+
+        Ok(rule)
+    }
+
+    /// A random rule no deeper than `depth`, using the indicators in `methods`
+    pub fn random(depth: usize, methods: &[u8], rng: &mut impl Rng) -> Rule {
+        if depth <= 1 || rng.gen_bool(0.3) {
+            return Value::comparison(methods, rng);
+        }
+
+        let operator = rng.gen_range(0..10);
+        let mut branch = || Box::new(Rule::random(depth - 1, methods, rng));
+
+        match operator {
+            0 => Rule::Not(branch()),
+            1..=5 => Rule::And(branch(), branch()),
+            _ => Rule::Or(branch(), branch()),
+        }
+    }
+
+    /// The number of rules and values in the tree
+    pub fn size(&self) -> usize {
+        match self {
+            Rule::And(a, b) | Rule::Or(a, b) => 1 + a.size() + b.size(),
+            Rule::Not(r) => 1 + r.size(),
+            Rule::Above(..) | Rule::Below(..) => 3,
+        }
+    }
+
+    /// The number of rules on the longest path from the root, counting comparisons
+    pub fn depth(&self) -> usize {
+        match self {
+            Rule::And(a, b) | Rule::Or(a, b) => 1 + a.depth().max(b.depth()),
+            Rule::Not(r) => 1 + r.depth(),
+            Rule::Above(..) | Rule::Below(..) => 1,
+        }
+    }
+
+    // The number of rules in the tree, which can each be swapped or replaced
+    fn nodes(&self) -> usize {
+        match self {
+            Rule::And(a, b) | Rule::Or(a, b) => 1 + a.nodes() + b.nodes(),
+            Rule::Not(r) => 1 + r.nodes(),
+            Rule::Above(..) | Rule::Below(..) => 1,
+        }
+    }
+
+    // The `n`th rule in preorder and how deep it is
+    fn node(&mut self, n: usize, depth: usize) -> (&mut Rule, usize) {
+        if n == 0 {
+            return (self, depth);
+        }
+
+        match self {
+            Rule::And(a, b) | Rule::Or(a, b) => {
+                let left = a.nodes();
+
+                if n <= left {
+                    a.node(n - 1, depth + 1)
+                } else {
+                    b.node(n - 1 - left, depth + 1)
+                }
+            }
+            Rule::Not(r) => r.node(n - 1, depth + 1),
+            Rule::Above(..) | Rule::Below(..) => unreachable!("Rule {n} is out of range"),
+        }
+    }
+
+    /// Replaces a random subtree with a new one, keeping the tree within `depth`
+    pub fn mutate(&mut self, depth: usize, methods: &[u8], rng: &mut impl Rng) {
+        let n = rng.gen_range(0..self.nodes());
+        let (node, at) = self.node(n, 1);
+
+        *node = Rule::random(depth.saturating_sub(at) + 1, methods, rng);
+    }
+
+    /// Swaps random subtrees between two rules. Children deeper than `depth` are
+    /// replaced by their parent.
+    pub fn crossover(a: &mut Rule, b: &mut Rule, depth: usize, rng: &mut impl Rng) {
+        let (mut x, mut y) = (a.clone(), b.clone());
+        let (n, m) = (rng.gen_range(0..x.nodes()), rng.gen_range(0..y.nodes()));
+
+        std::mem::swap(x.node(n, 1).0, y.node(m, 1).0);
+
+        if x.depth() <= depth {
+            *a = x;
+        }
+
+        if y.depth() <= depth {
+            *b = y;
+        }
+    }
+
+    // The indicators in the tree, in the order `holds` reads their values
+    fn indicators(&self, out: &mut Vec<Indicator>) {
+        match self {
+            Rule::And(a, b) | Rule::Or(a, b) => {
+                a.indicators(out);
+                b.indicators(out);
+            }
+            Rule::Not(r) => r.indicators(out),
+            Rule::Above(x, y) | Rule::Below(x, y) => {
+                for v in [x, y] {
+                    if let Value::Indicator(indicator) = v {
+                        out.push(*indicator);
+                    }
+                }
+            }
+        }
+    }
+
+    // Whether the rule holds given the day's price and indicator values. Every
+    // value is read, so `next` stays in step with `indicators`.
+    fn holds(&self, price: f64, values: &[Option<f64>], next: &mut usize) -> bool {
+        let mut value = |v: &Value| match v {
+            Value::Close => Some(price),
+            Value::Indicator(_) => {
+                *next += 1;
+                values[*next - 1]
+            }
+            Value::Constant(c) => Some(*c),
+        };
+
+        match self {
+            Rule::And(a, b) => {
+                let a = a.holds(price, values, next);
+                b.holds(price, values, next) && a
+            }
+            Rule::Or(a, b) => {
+                let a = a.holds(price, values, next);
+                b.holds(price, values, next) || a
+            }
+            Rule::Not(r) => !r.holds(price, values, next),
+            Rule::Above(x, y) => match (value(x), value(y)) {
+                (Some(x), Some(y)) => x > y,
+                _ => false,
+            },
+            Rule::Below(x, y) => match (value(x), value(y)) {
+                (Some(x), Some(y)) => x < y,
+                _ => false,
+            },
+        }
+    }
+
+    /// Whether the rule holds on each day of the market's history `ticker`
+    pub fn signals(&self, market: &Market, ticker: usize) -> Vec<bool> {
+        let mut indicators = Vec::new();
+        self.indicators(&mut indicators);
+
+        let history = &market.histories[ticker];
+        let series: Vec<_> = indicators.into_iter().map(|i| market.series(ticker, i)).collect();
+
+        (0..history.len())
+            .map(|day| {
+                let values: Vec<Option<f64>> = series.iter().map(|s| s[day]).collect();
+                self.holds(history.close[day], &values, &mut 0)
+            })
+            .collect()
+    }
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    at: usize,
+}
+
+impl Parser<'_> {
+    fn skip_spaces(&mut self) {
+        while self.text[self.at..].starts_with(' ') {
+            self.at += 1;
+        }
+    }
+
+    fn eat(&mut self, token: &str) -> bool {
+        self.skip_spaces();
+
+        if self.text[self.at..].starts_with(token) {
+            self.at += token.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, token: &str) -> std::result::Result<(), String> {
+        if self.eat(token) {
+            Ok(())
+        } else {
+            Err(format!("Expected '{token}' at {} in rule {}", self.at, self.text))
+        }
+    }
+
+    fn rule(&mut self) -> std::result::Result<Rule, String> {
+        if self.eat("!") {
+            return Ok(Rule::Not(Box::new(self.rule()?)));
+        }
+
+        if self.eat("(") {
+            let a = self.rule()?;
+
+            let rule = if self.eat("&") {
+                Rule::And(Box::new(a), Box::new(self.rule()?))
+            } else if self.eat("|") {
+                Rule::Or(Box::new(a), Box::new(self.rule()?))
+            } else {
+                a
+            };
+
+            self.expect(")")?;
+            return Ok(rule);
+        }
+
+        let a = self.value()?;
+
+        if self.eat(">") {
+            Ok(Rule::Above(a, self.value()?))
+        } else if self.eat("<") {
+            Ok(Rule::Below(a, self.value()?))
+        } else {
+            Err(format!("Expected a comparison at {} in rule {}", self.at, self.text))
+        }
+    }
+
+    fn value(&mut self) -> std::result::Result<Value, String> {
+        self.skip_spaces();
+
+        let rest = &self.text[self.at..];
+        let name: String = rest.chars().take_while(|c| c.is_ascii_lowercase()).collect();
+
+        if name.is_empty() {
+            let number: String = rest
+                .chars()
+                .take_while(|c| c.is_ascii_digit() || *c == '.' || *c == '-')
+                .collect();
+            self.at += number.len();
+
+            return number
+                .parse()
+                .map(Value::Constant)
+                .map_err(|_| format!("Expected a value at {} in rule {}", self.at, self.text));
+        }
+
+        self.at += name.len();
+
+        if name == "close" {
+            return Ok(Value::Close);
+        }
+
+        self.expect("(")?;
+        let rest = &self.text[self.at..];
+        let digits: String = rest.chars().take_while(char::is_ascii_digit).collect();
+        self.at += digits.len();
+        self.expect(")")?;
+
+        let days = digits
+            .parse()
+            .map_err(|_| format!("Expected days for {name} in rule {}", self.text))?;
+
+        Value::indicator(&name, days)
+            .map(Value::Indicator)
+            .ok_or_else(|| format!("Unknown indicator {name} in rule {}", self.text))
+    }
+}
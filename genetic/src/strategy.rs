@@ -0,0 +1,307 @@
+//! Stock strategies in a structured form, for reading genotypes such as
+//! `s020&r714|v715/e010|e010|b120/l050t100p250h005/b` with clear errors instead of
+//! panics, writing them back, and describing them in words, e.g. "buy when price >
+//! 20-day SMA AND 14-day RSI > 70 AND volume > 1.4× its 15-day average".
+
+use crate::problems::stocks::{Average, Direction, Indicator, Market, Stops};
+use std::fmt::{Display, Formatter, Result};
+
+// Bytes in three genes joined by operators
+const CONDITION_LEN: usize = 14;
+
+/// A stock strategy: the genes that buy, the exit genes and stops evolved with
+/// `--exits`, and the direction evolved with `--short`
+#[derive(Clone, Debug, PartialEq)]
+pub struct Strategy {
+    pub entry: Condition,
+    pub exit: Option<Condition>,
+    pub stops: Option<Stops>,
+    pub direction: Option<Direction>,
+}
+
+/// Three genes joined by `&` or `|`. The first operator decides whether all of
+/// them must hold or any one, and the second is only checked for being either.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Condition {
+    pub indicators: [Indicator; 3],
+    pub operators: [u8; 2],
+}
+
+impl Strategy {
+    /// Reads a genotype: genes, then optionally exit genes and stops, and a
+    /// direction, each after a `/`
+    pub fn decode(genotype: &[u8]) -> std::result::Result<Strategy, String> {
+        let mut parts = Vec::new();
+        let mut start = 0;
+
+        for part in genotype.split(|c| *c == b'/') {
+            parts.push((start, part));
+            start += part.len() + 1;
+        }
+
+        let (entry, exit, direction) = match parts[..] {
+            [entry] => (entry, None, None),
+            [entry, direction] => (entry, None, Some(direction)),
+            [entry, exit, stops] => (entry, Some((exit, stops)), None),
+            [entry, exit, stops, direction] => (entry, Some((exit, stops)), Some(direction)),
+            _ => {
+                return Err(format!(
+                    "{} parts, expected genes followed by exit genes and stops, a direction \
+                     or both, each after a /",
+                    parts.len()
+                ))
+            }
+        };
+
+        let entry = Condition::decode(entry)?;
+        let (exit, stops) = match exit {
+            Some((exit, (start, stops))) => (
+                Some(Condition::decode(exit)?),
+                Some(Stops::parse(stops).map_err(|e| format!("{e} at byte {start}"))?),
+            ),
+            None => (None, None),
+        };
+        let direction = match direction {
+            Some((start, [gene])) => {
+                Some(Direction::parse(*gene).map_err(|e| format!("{e} at byte {start}"))?)
+            }
+            Some((start, gene)) => {
+                return Err(format!(
+                    "direction {} at byte {start} should be a single l, s or b",
+                    String::from_utf8_lossy(gene)
+                ))
+            }
+            None => None,
+        };
+
+        Ok(Strategy {
+            entry,
+            exit,
+            stops,
+            direction,
+        })
+    }
+
+    /// Writes the strategy as a genotype, unless it has values no genotype can hold,
+    /// e.g. an RSI level that isn't a multiple of 10
+    pub fn encode(&self) -> std::result::Result<Vec<u8>, String> {
+        let mut genotype = self.entry.encode()?;
+
+        match (&self.exit, &self.stops) {
+            (Some(exit), Some(stops)) => {
+                genotype.push(b'/');
+                genotype.extend(exit.encode()?);
+                genotype.push(b'/');
+                genotype.extend(encode_stops(stops)?);
+            }
+            (None, None) => {}
+            _ => return Err("Exit genes and stops must be given together".to_string()),
+        }
+
+        if let Some(direction) = self.direction {
+            genotype.push(b'/');
+            genotype.push(direction.gene());
+        }
+
+        Ok(genotype)
+    }
+}
+
+impl Condition {
+    // Reads three genes joined by operators, starting at byte `start` of a genotype
+    fn decode((start, genes): (usize, &[u8])) -> std::result::Result<Condition, String> {
+        if genes.len() != CONDITION_LEN {
+            return Err(format!(
+                "genes {} at byte {start} are {} bytes, expected {CONDITION_LEN} such as \
+                 s020&r714|v715",
+                String::from_utf8_lossy(genes),
+                genes.len()
+            ));
+        }
+
+        let operator = |i: usize| match genes[i] {
+            b'&' | b'|' => Ok(genes[i]),
+            c => Err(format!("expected & or | at byte {}, found {}", start + i, c as char)),
+        };
+        let indicator = |i: usize| {
+            Market::parse(genes[i..i + 4].try_into().unwrap())
+                .map_err(|e| format!("{e} at byte {}", start + i))
+        };
+
+        Ok(Condition {
+            indicators: [indicator(0)?, indicator(5)?, indicator(10)?],
+            operators: [operator(4)?, operator(9)?],
+        })
+    }
+
+    fn encode(&self) -> std::result::Result<Vec<u8>, String> {
+        let mut genes = Vec::with_capacity(CONDITION_LEN);
+
+        for (i, indicator) in self.indicators.iter().enumerate() {
+            if i > 0 {
+                match self.operators[i - 1] {
+                    b'&' | b'|' => genes.push(self.operators[i - 1]),
+                    c => return Err(format!("Invalid operator {}, expected & or |", c as char)),
+                }
+            }
+
+            genes.extend(encode_gene(indicator)?);
+        }
+
+        Ok(genes)
+    }
+}
+
+impl Display for Strategy {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        write!(f, "buy when {}", self.entry)?;
+
+        match &self.exit {
+            Some(exit) => write!(f, ", sell when {exit}")?,
+            None => write!(f, ", otherwise sell")?,
+        }
+
+        if let Some(stops) = &self.stops {
+            let mut clauses = Vec::new();
+
+            if stops.loss > 0.0 {
+                clauses.push(format!("a {} stop-loss", percent(stops.loss)));
+            }
+
+            if stops.trailing > 0.0 {
+                clauses.push(format!("a {} trailing stop", percent(stops.trailing)));
+            }
+
+            if stops.profit > 0.0 {
+                clauses.push(format!("a {} take-profit", percent(stops.profit)));
+            }
+
+            if stops.hold > 0 {
+                clauses.push(format!("holding at least {} days before selling", stops.hold));
+            }
+
+            if !clauses.is_empty() {
+                write!(f, ", with {}", clauses.join(", "))?;
+            }
+        }
+
+        match self.direction {
+            Some(Direction::Long) => write!(f, ", trading long"),
+            Some(Direction::Short) => write!(f, ", trading short"),
+            Some(Direction::Both) => write!(f, ", trading long and short"),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Display for Condition {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        let joiner = if self.operators[0] == b'&' { "AND" } else { "OR" };
+        let [a, b, c] = self.indicators.map(describe);
+
+        write!(f, "{a} {joiner} {b} {joiner} {c}")
+    }
+}
+
+// When a gene is bullish, in words
+fn describe(indicator: Indicator) -> String {
+    match indicator {
+        Indicator::Average(average) => {
+            let (days, name) = match average {
+                Average::Simple(days) => (days, "SMA"),
+                Average::Exponential(days) => (days, "EMA"),
+                Average::Weighted(days) => (days, "WMA"),
+                Average::Maximum(days) => (days, "max"),
+                Average::Minimum(days) => (days, "min"),
+            };
+
+            format!("price > {days}-day {name}")
+        }
+        Indicator::Rsi { days, level } => format!("{days}-day RSI > {level}"),
+        Indicator::Macd(days) => format!("{days}-day MACD > signal"),
+        Indicator::Bollinger { days, deviations } => {
+            format!("price > {days}-day mean {deviations:+} standard deviations")
+        }
+        Indicator::RateOfChange { days, percent } => format!("{days}-day change > {percent}%"),
+        Indicator::Volume { days, ratio } => {
+            format!("volume > {ratio}× its {days}-day average")
+        }
+    }
+}
+
+// The gene for an indicator. Averages have three digits of days and the others a
+// digit for their threshold and two of days.
+fn encode_gene(indicator: &Indicator) -> std::result::Result<Vec<u8>, String> {
+    let digit = |value: f64, expected: &str| {
+        let rounded = value.round();
+
+        if (value - rounded).abs() < 1e-9 && (0.0..=9.0).contains(&rounded) {
+            Ok(rounded as usize)
+        } else {
+            Err(format!("Can't write {} as a gene: {expected}", describe(*indicator)))
+        }
+    };
+    let level = match *indicator {
+        Indicator::Average(_) | Indicator::Macd(_) => None,
+        Indicator::Rsi { level, .. } => {
+            Some(digit(level / 10.0, "the level must be a multiple of 10 up to 90")?)
+        }
+        Indicator::Bollinger { deviations, .. } => Some(digit(
+            deviations * 2.0 + 5.0,
+            "the deviations must be a multiple of 0.5 from -2.5 to 2",
+        )?),
+        Indicator::RateOfChange { percent, .. } => Some(digit(
+            percent + 5.0,
+            "the percentage must be a whole number from -5 to 4",
+        )?),
+        Indicator::Volume { ratio, .. } => {
+            Some(digit(ratio * 5.0, "the ratio must be a multiple of 0.2 up to 1.8")?)
+        }
+    };
+    let days = indicator.period();
+    let most = if level.is_some() { 99 } else { 999 };
+
+    if days > most {
+        return Err(format!(
+            "Can't write {} as a gene: it can have at most {most} days",
+            describe(*indicator)
+        ));
+    }
+
+    Ok(match level {
+        Some(level) => format!("{}{level}{days:02}", indicator.kind() as char),
+        None => format!("{}{days:03}", indicator.kind() as char),
+    }
+    .into_bytes())
+}
+
+fn encode_stops(stops: &Stops) -> std::result::Result<Vec<u8>, String> {
+    let tenths = |fraction: f64, name: &str| {
+        let value = (fraction * 1000.0).round();
+
+        if (fraction * 1000.0 - value).abs() < 1e-6 && (0.0..=999.0).contains(&value) {
+            Ok(value as usize)
+        } else {
+            Err(format!(
+                "Can't write a {} {name} as a stop: it must be a multiple of 0.1% up to 99.9%",
+                percent(fraction)
+            ))
+        }
+    };
+    let (loss, trailing, profit) = (
+        tenths(stops.loss, "stop-loss")?,
+        tenths(stops.trailing, "trailing stop")?,
+        tenths(stops.profit, "take-profit")?,
+    );
+
+    if stops.hold > 999 {
+        return Err(format!("Can't hold for {} days in a stop: at most 999", stops.hold));
+    }
+
+    Ok(format!("l{loss:03}t{trailing:03}p{profit:03}h{:03}", stops.hold).into_bytes())
+}
+
+// A fraction as a percentage, to a tenth of a percent
+fn percent(fraction: f64) -> String {
+    format!("{}%", (fraction * 1000.0).round() / 10.0)
+}
@@ -0,0 +1,253 @@
+use chrono::{Datelike, NaiveDate, Weekday};
+use clap::ValueEnum;
+use crate::problems::stocks::TRADING_DAYS;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::fmt::{Display, Formatter};
+
+// Prices are written to the cent, so none go below one
+const MIN_PRICE: f64 = 0.01;
+
+/// The random process a synthetic history follows
+#[derive(Clone, Copy, Debug, Default, PartialEq, ValueEnum)]
+pub enum Process {
+    /// Geometric Brownian motion, compounding the drift with normally
+    /// distributed returns
+    #[default]
+    Gbm,
+    /// An Ornstein-Uhlenbeck process pulling the log price back towards the
+    /// starting price, which grows at the drift
+    MeanReverting,
+    /// Geometric Brownian motion that switches between a bull market with the
+    /// drift and a bear market with the opposite drift and twice the volatility
+    RegimeSwitching,
+    /// A straight line growing at the drift, plus independent noise each day
+    TrendNoise,
+    /// Geometric Brownian motion with occasional jumps in the price
+    Jump,
+}
+
+/// Options for a synthetic price history
+#[derive(clap::Args, Clone, Debug)]
+pub struct Options {
+    /// The file to write the history to, in the plain-text format, or standard
+    /// output if not given
+    pub file: Option<String>,
+
+    /// The random process the prices follow
+    #[arg(long, value_enum, default_value_t = Process::Gbm)]
+    pub process: Process,
+
+    /// Yearly growth, e.g. 0.05 for 5%
+    #[arg(long, default_value_t = 0.05, allow_negative_numbers = true)]
+    pub drift: f64,
+
+    /// Yearly standard deviation of returns, e.g. 0.2 for 20%
+    #[arg(long, default_value_t = 0.2)]
+    pub volatility: f64,
+
+    /// The number of trading days
+    #[arg(long, default_value_t = 252)]
+    pub length: usize,
+
+    /// Seed for the random numbers, so the same seed writes the same prices
+    #[arg(long, default_value_t = 0)]
+    pub seed: u64,
+
+    /// The first price
+    #[arg(long, default_value_t = 100.0)]
+    pub price: f64,
+
+    /// The first trading day, which dates the history's range
+    #[arg(long, default_value = "2000-01-03")]
+    pub from: NaiveDate,
+
+    /// The ticker written on the first line
+    #[arg(long, default_value = "SYNTH")]
+    pub ticker: String,
+
+    /// How strongly a mean-reverting price is pulled back to its mean, as a yearly
+    /// rate, e.g. 5 closes about 2% of the distance a day
+    #[arg(long, default_value_t = 5.0)]
+    pub reversion: f64,
+
+    /// The chance each day of switching between bull and bear markets
+    #[arg(long, default_value_t = 0.01, value_parser = chance)]
+    pub switch_chance: f64,
+
+    /// The average number of jumps a year
+    #[arg(long, default_value_t = 4.0, value_parser = rate)]
+    pub jumps: f64,
+
+    /// The standard deviation of the log of each jump
+    #[arg(long, default_value_t = 0.1)]
+    pub jump_size: f64,
+}
+
+impl Display for Process {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Process::Gbm => write!(f, "Geometric Brownian motion"),
+            Process::MeanReverting => write!(f, "Mean-reverting"),
+            Process::RegimeSwitching => write!(f, "Regime-switching"),
+            Process::TrendNoise => write!(f, "Trend plus noise"),
+            Process::Jump => write!(f, "Jump diffusion"),
+        }
+    }
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options {
+            file: None,
+            process: Process::Gbm,
+            drift: 0.05,
+            volatility: 0.2,
+            length: 252,
+            seed: 0,
+            price: 100.0,
+            from: NaiveDate::from_ymd_opt(2000, 1, 3).unwrap(),
+            ticker: "SYNTH".to_string(),
+            reversion: 5.0,
+            switch_chance: 0.01,
+            jumps: 4.0,
+            jump_size: 0.1,
+        }
+    }
+}
+
+impl Options {
+    /// The daily closing prices, starting at the first price
+    pub fn prices(&self) -> Vec<f64> {
+        let mut rng = StdRng::seed_from_u64(self.seed);
+        let dt = 1.0 / TRADING_DAYS;
+        let (drift, volatility) = (self.drift, self.volatility);
+        let mut prices = Vec::with_capacity(self.length);
+        let mut price = self.price;
+        let mut bear = false;
+
+        for day in 0..self.length {
+            if day > 0 {
+                let z = normal(&mut rng);
+
+                price = match self.process {
+                    Process::Gbm => price * step(drift, volatility, dt, z),
+                    Process::MeanReverting => {
+                        let mean = self.price.ln() + drift * day as f64 * dt;
+                        let log = price.ln();
+
+                        (log + self.reversion * (mean - log) * dt + volatility * dt.sqrt() * z)
+                            .exp()
+                    }
+                    Process::RegimeSwitching => {
+                        if rng.gen_bool(self.switch_chance) {
+                            bear = !bear;
+                        }
+
+                        if bear {
+                            price * step(-drift, 2.0 * volatility, dt, z)
+                        } else {
+                            price * step(drift, volatility, dt, z)
+                        }
+                    }
+                    // the noise doesn't accumulate, so it's a day's worth of volatility
+                    Process::TrendNoise => {
+                        self.price * (1.0 + drift * day as f64 * dt + volatility * dt.sqrt() * z)
+                    }
+                    Process::Jump => {
+                        let jump = if rng.gen_bool((self.jumps * dt).min(1.0)) {
+                            (self.jump_size * normal(&mut rng)).exp()
+                        } else {
+                            1.0
+                        };
+
+                        price * step(drift, volatility, dt, z) * jump
+                    }
+                }
+                .max(MIN_PRICE);
+            }
+
+            prices.push(price);
+        }
+
+        prices
+    }
+
+    /// The history in the plain-text format: the ticker and process, the months
+    /// it spans, then one price a line, oldest first
+    pub fn text(&self) -> String {
+        let mut last = self.from;
+
+        for _ in 1..self.length {
+            last = next_weekday(last);
+        }
+
+        let mut s = format!(
+            "{} - {} (seed {})\n{} - {}\n",
+            self.ticker,
+            self.process,
+            self.seed,
+            self.from.format("%B %Y"),
+            last.format("%B %Y")
+        );
+
+        for price in self.prices() {
+            s += &format!("{price:.2}\n");
+        }
+
+        s
+    }
+
+    /// Write the history to its file, or print it if there's none
+    pub fn write(&self) {
+        let text = self.text();
+
+        match &self.file {
+            Some(file) => {
+                if let Err(e) = std::fs::write(file, text) {
+                    panic!("Error writing {file}: {e}");
+                }
+            }
+            None => print!("{text}"),
+        }
+    }
+}
+
+// A chance for --switch-chance, from 0 to 1
+fn chance(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(c) if (0.0..=1.0).contains(&c) => Ok(c),
+        Ok(_) => Err("must be from 0 to 1".to_string()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+// A yearly rate of events for --jumps, which can't be negative
+fn rate(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(r) if r >= 0.0 => Ok(r),
+        Ok(_) => Err("can't be negative".to_string()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+// The growth of a geometric Brownian motion over `dt` years
+fn step(drift: f64, volatility: f64, dt: f64, z: f64) -> f64 {
+    ((drift - volatility * volatility / 2.0) * dt + volatility * dt.sqrt() * z).exp()
+}
+
+// A standard normal sample, by the Box-Muller transform
+fn normal(rng: &mut impl Rng) -> f64 {
+    let u: f64 = 1.0 - rng.gen::<f64>();
+    let v: f64 = rng.gen();
+
+    (-2.0 * u.ln()).sqrt() * (2.0 * std::f64::consts::PI * v).cos()
+}
+
+fn next_weekday(day: NaiveDate) -> NaiveDate {
+    let next = day.succ_opt().expect("Date out of range");
+
+    match next.weekday() {
+        Weekday::Sat | Weekday::Sun => next_weekday(next),
+        _ => next,
+    }
+}
@@ -1,257 +1,2592 @@
-use rand::Rng;
-use crate::*;
+use crate::genetic::*;
+use crate::indicators::*;
+use crate::problems::{knapsack::Knapsack, stocks::*, Problem};
+use crate::rules::*;
+use crate::strategy::Strategy;
+use crate::synthetic::{self, Process};
+use clap::{Parser, ValueEnum};
+use indicatif::ProgressBar;
+use std::sync::Arc;
 
-// mutate((mut String, mut String), f64, Vec<char>, bool) -> (String, String)
+// A generation over a knapsack that can hold every item,
+// so every genotype of the right length is valid
+fn generation(length: usize, sex_method: SexMethod, skip: f64) -> Generation {
+    Generation {
+        best: 1,
+        force_create: false,
+        detect_crowding: 0.0,
+        max_generations: 1,
+        force_mutation: false,
+        population: Vec::new(),
+        intermediate: Vec::new(),
+        skip,
+        mutation_rate: 0.0,
+        problem: Box::new(Knapsack {
+            alphabet: vec![b'0', b'1'],
+            items: vec![(1, 1); length],
+            max_weight: length,
+        }),
+        selection_method: SelectionMethod::Equal,
+        sex_method,
+        progress: ProgressBar::hidden(),
+        interrupt: None,
+    }
+}
+
+fn genotype(s: &str) -> Genotype {
+    Genotype::from(s.bytes().collect(), Fitness::Invalid)
+}
+
+// Count the loci where a child switches between inheriting from each parent
+fn crossover_points(child: &Genotype, parent: &Genotype) -> usize {
+    child
+        .genotype
+        .windows(2)
+        .zip(parent.genotype.windows(2))
+        .filter(|(c, p)| (c[0] == p[0]) != (c[1] == p[1]))
+        .count()
+}
+
+fn market(file: &str) -> Market {
+    Market::new(vec![file.to_string()]).unwrap()
+}
+
+fn assert_close(a: f64, b: f64) {
+    assert!((a - b).abs() < 1e-9, "{a} != {b}");
+}
+
+// one-year-linear.txt is 0.01, 0.02, 0.03, ...
+fn linear(day: usize) -> f64 {
+    0.01 * (day + 1) as f64
+}
+
+// one-year-sinusoidal.txt rises from 1.00 to 1.50 and back every 100 days
+fn sinusoidal(day: usize) -> f64 {
+    let x = day % 100;
+    1.0 + 0.01 * x.min(100 - x) as f64
+}
+
+fn exponential(price: impl Fn(usize) -> f64, day: usize, days: usize) -> f64 {
+    let a = 1.0 - 2.0 / (days as f64 + 1.0);
+    let (n, d) = (0..days).fold((0.0, 0.0), |(n, d), k| {
+        (
+            n + a.powi(k as i32) * price(day - 1 - k),
+            d + a.powi(k as i32),
+        )
+    });
 
-// Ensure mutated genotypes are different
+    n / d
+}
+
+// Genotype::reproduce(&mut self, &mut Genotype, &Generation)
+
+// Ensure that parents/genitors have a chance to carry over to the next generation
 #[test]
-fn mutate_works() {
-    let child = (String::from("0123456789ABCDEF"), String::from("FEDCBA9876543210"));
-    let mutated = 
-        mutate(
-            child.clone(),
-            1.0,
-            vec!['0', '1', '2', '3', '4', '5', '6', '7', '8', '9', 'A', 'B', 'C', 'D', 'E', 'F'],
-            true
-        );
-    
-    assert_ne!(child, mutated, );
+fn reproduce_carry_over_parents() {
+    let generation = generation(16, SexMethod::Uniform, 1.0);
+    let parent = (genotype("0000000000000000"), genotype("1111111111111111"));
+    let mut child = parent.clone();
+
+    child.0.reproduce(&mut child.1, &generation);
+
+    assert_eq!(parent.0.genotype, child.0.genotype);
+    assert_eq!(parent.1.genotype, child.1.genotype);
+}
+
+// Ensure resulting children share genes with parents
+#[test]
+fn reproduce_children_inherit_parent_genes() {
+    for sex_method in [SexMethod::One, SexMethod::Two, SexMethod::Uniform] {
+        let generation = generation(16, sex_method, 0.0);
+        let parent = (genotype("0123456789ABCDEF"), genotype("FEDCBA9876543210"));
+        let mut child = parent.clone();
+
+        child.0.reproduce(&mut child.1, &generation);
+
+        for i in 0..16 {
+            let (c0, c1) = (child.0.genotype[i], child.1.genotype[i]);
+            let (p0, p1) = (parent.0.genotype[i], parent.1.genotype[i]);
+
+            assert!((c0 == p0 && c1 == p1) || (c0 == p1 && c1 == p0));
+        }
+    }
+}
+
+// Ensure reproduction recalculates the fitness of the children
+#[test]
+fn reproduce_updates_fitness() {
+    let generation = generation(16, SexMethod::Two, 0.0);
+    let mut child = (genotype("0000000000000000"), genotype("1111111111111111"));
+
+    child.0.reproduce(&mut child.1, &generation);
+
+    assert_eq!(
+        child.0.fitness,
+        generation.problem.fitness(&child.0.genotype)
+    );
+    assert_eq!(
+        child.1.fitness,
+        generation.problem.fitness(&child.1.genotype)
+    );
+}
+
+// Ensure reproduction method (number of crossover points) is respected
+#[test]
+fn reproduce_one_crossover_point() {
+    let generation = generation(16, SexMethod::One, 0.0);
+    let parent = genotype("0000000000000000");
+    let mut child = (parent.clone(), genotype("1111111111111111"));
+
+    child.0.reproduce(&mut child.1, &generation);
+
+    assert_eq!(crossover_points(&child.0, &parent), 1);
+}
+
+// Ensure reproduction method (number of crossover points) is respected
+#[test]
+fn reproduce_two_crossover_points() {
+    let generation = generation(16, SexMethod::Two, 0.0);
+    let parent = genotype("0000000000000000");
+    let mut child = (parent.clone(), genotype("1111111111111111"));
+
+    child.0.reproduce(&mut child.1, &generation);
+
+    assert_eq!(crossover_points(&child.0, &parent), 2);
+}
+
+// Ensure reproduction method (number of crossover points) is respected
+#[test]
+fn reproduce_uniform_crossover_points() {
+    let generation = generation(16, SexMethod::Uniform, 0.0);
+    let parent = genotype("0000000000000000");
+    let mut child = (parent.clone(), genotype("1111111111111111"));
+
+    child.0.reproduce(&mut child.1, &generation);
+
+    assert!(crossover_points(&child.0, &parent) > 2);
 }
 
+// Problem::mutate(&self, f64, bool, &mut Genotype)
+
 // Ensure non-mutated genotypes are not different
 #[test]
 fn mutate_probability_works() {
-    let child = (String::from("0123456789ABCDEF"), String::from("FEDCBA9876543210"));
-    let mutated = 
-        mutate(
-            child.clone(),
-            0.0,
-            vec!['0', '1', '2', '3', '4', '5', '6', '7', '8', '9', 'A', 'B', 'C', 'D', 'E', 'F'],
-            false
-        );
-    
-    assert_eq!(child, mutated, );
+    let knapsack = generation(16, SexMethod::One, 0.0).problem;
+    let stocks = market("testdata/tests/one-month-linear.txt");
+
+    let mut g = genotype("0110100110010110");
+    knapsack.mutate(0.0, true, &mut g);
+    assert_eq!(g.genotype, b"0110100110010110");
+
+    let mut g = genotype("e010|m002|s101");
+    stocks.mutate(0.0, true, &mut g);
+    assert_eq!(g.genotype, b"e010|m002|s101");
 }
 
 // Ensure that if mutations are forced, all of
 // the mutated genes have different allelles
 #[test]
 fn mutate_forced_mutations() {
-    let child = (String::from("ABCD"), String::from("DCBA"));
-    let mutated = 
-        mutate(
-            child.clone(),
-            1.0,
-            vec!['A', 'B', 'C', 'D'],
-            true
-        );
+    let knapsack = generation(16, SexMethod::One, 0.0).problem;
+    let stocks = market("testdata/tests/one-month-linear.txt");
 
-    for (old, new) in child.0.chars().zip(mutated.0.chars()) {
+    let old = genotype("0110100110010110");
+    let mut new = old.clone();
+    knapsack.mutate(1.0, true, &mut new);
+
+    for (old, new) in old.genotype.iter().zip(new.genotype.iter()) {
         assert_ne!(old, new);
     }
 
-    for (old, new) in child.1.chars().zip(mutated.1.chars()) {
+    let old = genotype("e010|m002&s199");
+    let mut new = old.clone();
+    stocks.mutate(1.0, true, &mut new);
+
+    for (old, new) in old.genotype.iter().zip(new.genotype.iter()) {
         assert_ne!(old, new);
     }
 }
 
-// Be mostly sure that if mutations are not forced, 
+// Be mostly sure that if mutations are not forced,
 // not all of the mutated genes have different allelles
 #[test]
 fn mutate_unforced_mutations() {
-    let mutated = 
-        mutate(
-            (
-                String::from("AAAAAAAAAAAAAAAA"),
-                String::from("BBBBBBBBBBBBBBBB")
-            ),
-            1.0,
-            vec!['A', 'B'],
-            false
-        );
+    let knapsack = generation(16, SexMethod::One, 0.0).problem;
+    let mut g = genotype("0000000000000000");
 
-        assert!(mutated.0.contains('B'), "Holy moly, the chances of this failing are 0.000015%");
-        assert!(mutated.1.contains('A'), "Holy moly, the chances of this failing are 0.000015%");
-}
+    knapsack.mutate(1.0, false, &mut g);
 
-// reproduce((String, String), SexMethod, Vec<char>, f64, f64, bool) -> (String, String)
+    assert!(
+        g.genotype.contains(&b'0'),
+        "Holy moly, the chances of this failing are 0.0015%"
+    );
+    assert!(
+        g.genotype.contains(&b'1'),
+        "Holy moly, the chances of this failing are 0.0015%"
+    );
+}
 
-// Ensure that parents/genitors have a chance to carry over to the next generation
+// Ensure mutated stock genotypes can still be parsed and evaluated
 #[test]
-fn reproduce_carry_over_parents() {
-    let parent = (String::from("ABCD"), String::from("DCBA"));
+fn mutate_stocks_stays_valid() {
+    let stocks = market("testdata/tests/one-month-linear.txt");
+
+    for force in [false, true] {
+        let mut g = genotype("e010|m002|s101");
+
+        for _ in 0..100 {
+            stocks.mutate(0.5, force, &mut g);
+            assert_eq!(g.len(), stocks.len());
+            assert!(matches!(g.fitness, Fitness::Valid(_)));
+        }
+    }
+}
+
+// Generation::select_genitors(&mut self)
+
+// A population where one genotype is much fitter than the rest
+fn selection(selection_method: SelectionMethod) -> Generation {
+    let mut generation = generation(4, SexMethod::One, 0.0);
+    generation.selection_method = selection_method;
+    generation.population = vec![
+        Genotype::from(b"0001".to_vec(), Fitness::Valid(1.0)),
+        Genotype::from(b"0010".to_vec(), Fitness::Valid(1.0)),
+        Genotype::from(b"1111".to_vec(), Fitness::Valid(100.0)),
+        Genotype::from(b"0100".to_vec(), Fitness::Valid(1.0)),
+        Genotype::from(b"1000".to_vec(), Fitness::Invalid),
+    ];
+    generation.intermediate = Vec::with_capacity(generation.population.len() * 2);
+
+    generation.select_genitors();
+
+    generation
+}
+
+fn count(generation: &Generation, g: &[u8]) -> usize {
+    generation
+        .intermediate
+        .iter()
+        .filter(|i| i.genotype == g)
+        .count()
+}
 
+// Ensure the pool is filled from the population, best first
+fn check_selection(generation: &Generation) {
     assert_eq!(
-        parent,
-        reproduce(
-            parent.clone(),
-            SexMethod::Uniform,
-            vec!['A', 'B', 'C', 'D'],
-            0.0,
-            1.0,
-            false
-        )
+        generation.intermediate.len(),
+        generation.population.len() * 2
     );
+
+    for g in &generation.intermediate {
+        assert!(generation
+            .population
+            .iter()
+            .any(|p| p.genotype == g.genotype && p.fitness == g.fitness));
+    }
+
+    for pair in generation.intermediate.windows(2) {
+        assert!(pair[0].fitness >= pair[1].fitness);
+    }
 }
 
-// Ensure resulting children share genes with parents
 #[test]
-fn reproduce_children_inherit_parent_genes() {
-    let parent = (String::from("ABCD"), String::from("DCBA"));
-    let child = 
-        reproduce(
-            parent.clone(),
-            SexMethod::Uniform,
-            vec!['A', 'B', 'C', 'D'],
-            0.0,
-            0.0,
-            false
-        );
+fn select_equal() {
+    let generation = selection(SelectionMethod::Equal);
 
-    assert!(
-        child.0.chars().zip(parent.0.chars().zip(parent.1.chars()))
-            .fold(true, |same, (c, (p0, p1))| same && (c == p0 || c == p1))
-    );
+    check_selection(&generation);
+}
 
-    assert!(
-        child.1.chars().zip(parent.0.chars().zip(parent.1.chars()))
-            .fold(true, |same, (c, (p0, p1))| same && (c == p0 || c == p1))
+// Ensure the fittest genotype takes up most of the pool
+#[test]
+fn select_replacement() {
+    let generation = selection(SelectionMethod::Replacement);
+
+    check_selection(&generation);
+    assert!(count(&generation, b"1111") > generation.intermediate.len() / 2);
+}
+
+// Ensure each genotype is copied into the pool once per multiple of the
+// average fitness, and invalid genotypes are never selected
+#[test]
+fn select_remainder() {
+    let generation = selection(SelectionMethod::Remainder);
+
+    check_selection(&generation);
+    assert!(count(&generation, b"1111") >= generation.intermediate.len() - 3);
+    assert_eq!(count(&generation, b"1000"), 0);
+}
+
+// Market::get_average(&[f64], usize, &Average) -> f64
+
+// Ensure the test data is what the expected values assume
+#[test]
+fn market_test_data() {
+    let m = market("testdata/tests/one-year-linear.txt");
+    assert_eq!(m.histories[0].len(), 356);
+    m.histories[0].close
+        .iter()
+        .enumerate()
+        .for_each(|(day, p)| assert_close(*p, linear(day)));
+
+    let m = market("testdata/tests/one-year-sinusoidal.txt");
+    assert_eq!(m.histories[0].len(), 351);
+    m.histories[0].close
+        .iter()
+        .enumerate()
+        .for_each(|(day, p)| assert_close(*p, sinusoidal(day)));
+}
+
+// Ensure averages are zero until there are enough days to compute them
+#[test]
+fn market_average_not_enough_days() {
+    let m = market("testdata/tests/one-year-linear.txt");
+    let stock = &m.histories[0].close;
+
+    for average in [
+        Average::Simple(10),
+        Average::Exponential(10),
+        Average::Maximum(10),
+    ] {
+        assert_eq!(Market::get_average(stock, 9, &average), 0.0);
+    }
+
+    assert_eq!(
+        Market::get_average(stock, 100, &Average::Simple(0)),
+        0.0
     );
 }
 
-// Ensure reproduction method (number of crossover points) is respected
 #[test]
-fn reproduce_one_crossover_point() {
-    let parent = (String::from("0123456789ABCDEF"), String::from("FEDCBA9876543210"));
-    let child = 
-        reproduce(
-            parent.clone(),
-            SexMethod::One,
-            vec!['0', '1', '2', '3', '4', '5', '6', '7', '8', '9', 'A', 'B', 'C', 'D', 'E', 'F'],
-            0.0,
-            0.0,
-            false
+fn market_simple_moving_average() {
+    let m = market("testdata/tests/one-year-linear.txt");
+    let stock = &m.histories[0].close;
+
+    for days in [1, 2, 10, 50, 200] {
+        for day in days..stock.len() {
+            let average = Market::get_average(stock, day, &Average::Simple(days));
+
+            // the mean of 0.01 * (day - days + 1) ..= 0.01 * day
+            assert_close(average, 0.01 * (2 * day - days + 1) as f64 / 2.0);
+        }
+    }
+
+    let m = market("testdata/tests/one-year-sinusoidal.txt");
+    let stock = &m.histories[0].close;
+
+    // a whole period averages to the midpoint
+    for day in 100..stock.len() {
+        assert_close(
+            Market::get_average(stock, day, &Average::Simple(100)),
+            1.25,
+        );
+    }
+
+    for day in 10..stock.len() {
+        let expected = (day - 10..day).map(sinusoidal).sum::<f64>() / 10.0;
+        assert_close(
+            Market::get_average(stock, day, &Average::Simple(10)),
+            expected,
         );
+    }
+}
+
+// Ensure the streaming simple average matches computing it from scratch
+#[test]
+fn market_simple_moving_average_incremental() {
+    for file in [
+        "testdata/tests/one-year-linear.txt",
+        "testdata/tests/one-year-sinusoidal.txt",
+    ] {
+        let m = market(file);
+        let stock = &m.histories[0].close;
 
-    let mut test = child.0[0..1] == parent.0[0..1] && child.1[0..1] == parent.1[0..1];
+        for days in [1, 7, 30] {
+            let average = Average::Simple(days);
+            let mut sma = Sma::new(days);
 
-    assert_eq!(
-        child.0.chars().zip(child.1.chars())
-            .zip(parent.0.chars().zip(parent.1.chars()))
-            .fold(0, |num_points, ((c0, c1), (p0, p1))| {
-                if test == (c0 == p0 && c1 == p1) {
-                    num_points
+            for (day, price) in stock.iter().enumerate() {
+                if day < days {
+                    assert_eq!(sma.value(), None);
                 } else {
-                    test = !test;
-                    num_points + 1
+                    assert_close(sma.value().unwrap(), Market::get_average(stock, day, &average));
                 }
+
+                sma.push(*price);
             }
-        ),
-        1
-    );
+        }
+    }
+}
+
+#[test]
+fn market_exponential_moving_average() {
+    let m = market("testdata/tests/one-year-linear.txt");
+    let stock = &m.histories[0].close;
 
+    for days in [1, 2, 10, 50] {
+        for day in days..stock.len() {
+            assert_close(
+                Market::get_average(stock, day, &Average::Exponential(days)),
+                exponential(linear, day, days),
+            );
+        }
+    }
+
+    let m = market("testdata/tests/one-year-sinusoidal.txt");
+    let stock = &m.histories[0].close;
+
+    for days in [1, 2, 10, 50] {
+        for day in days..stock.len() {
+            assert_close(
+                Market::get_average(stock, day, &Average::Exponential(days)),
+                exponential(sinusoidal, day, days),
+            );
+        }
+    }
 }
 
-// Ensure reproduction method (number of crossover points) is respected
 #[test]
-fn reproduce_two_crossover_point() {
-    let parent = (String::from("0123456789ABCDEF"), String::from("FEDCBA9876543210"));
-    let child = 
-        reproduce(
-            parent.clone(),
-            SexMethod::Two,
-            vec!['0', '1', '2', '3', '4', '5', '6', '7', '8', '9', 'A', 'B', 'C', 'D', 'E', 'F'],
-            0.0,
-            0.0,
-            false
+fn market_maximum_moving_average() {
+    let m = market("testdata/tests/one-year-linear.txt");
+    let stock = &m.histories[0].close;
+
+    // the most recent price is always the highest
+    for days in [1, 2, 10, 50] {
+        for day in days..stock.len() {
+            assert_close(
+                Market::get_average(stock, day, &Average::Maximum(days)),
+                linear(day - 1),
+            );
+        }
+    }
+
+    let m = market("testdata/tests/one-year-sinusoidal.txt");
+    let stock = &m.histories[0].close;
+
+    // every window of a whole period contains a peak
+    for day in 100..stock.len() {
+        assert_close(
+            Market::get_average(stock, day, &Average::Maximum(100)),
+            1.5,
         );
+    }
 
-    let mut test = child.0[0..1] == parent.0[0..1] && child.1[0..1] == parent.1[0..1];
+    for days in [1, 10, 50] {
+        let average = Average::Maximum(days);
+        let mut maximum = Extreme::max(days);
 
-    assert_eq!(
-        child.0.chars().zip(child.1.chars())
-            .zip(parent.0.chars().zip(parent.1.chars()))
-            .fold(0, |num_points, ((c0, c1), (p0, p1))| {
-                if test == (c0 == p0 && c1 == p1) {
-                    num_points
-                } else {
-                    test = !test;
-                    num_points + 1
-                }
+        for price in &stock[..days] {
+            maximum.push(*price);
+        }
+
+        for day in days..stock.len() {
+            let expected = (day - days..day).map(sinusoidal).fold(0.0, f64::max);
+
+            assert_close(
+                Market::get_average(stock, day, &average),
+                expected,
+            );
+            assert_close(maximum.value().unwrap(), expected);
+            maximum.push(stock[day]);
+        }
+    }
+}
+
+#[test]
+fn market_minimum_moving_average() {
+    let m = market("testdata/tests/one-year-linear.txt");
+    let stock = &m.histories[0].close;
+
+    // the oldest price is always the lowest
+    for days in [1, 2, 10, 50] {
+        for day in days..stock.len() {
+            assert_close(
+                Market::get_average(stock, day, &Average::Minimum(days)),
+                linear(day - days),
+            );
+        }
+    }
+
+    let m = market("testdata/tests/one-year-sinusoidal.txt");
+    let stock = &m.histories[0].close;
+
+    for days in [1, 10, 50, 100] {
+        for day in days..stock.len() {
+            let expected = (day - days..day).map(sinusoidal).fold(f64::MAX, f64::min);
+            assert_close(
+                Market::get_average(stock, day, &Average::Minimum(days)),
+                expected,
+            );
+        }
+    }
+}
+
+#[test]
+fn market_weighted_moving_average() {
+    for (file, price) in [
+        ("testdata/tests/one-year-linear.txt", linear as fn(usize) -> f64),
+        ("testdata/tests/one-year-sinusoidal.txt", sinusoidal),
+    ] {
+        let m = market(file);
+        let stock = &m.histories[0].close;
+
+        for days in [1, 2, 10, 50] {
+            for day in days..stock.len() {
+                let weights = (1..=days).map(|w| w as f64);
+                let expected = (day - days..day).zip(weights.clone()).map(|(d, w)| price(d) * w);
+                let expected = expected.sum::<f64>() / weights.sum::<f64>();
+
+                assert_close(
+                    Market::get_average(stock, day, &Average::Weighted(days)),
+                    expected,
+                );
             }
-        ),
-        2
+        }
+    }
+}
+
+fn market_with(file: &str, options: Options) -> Market {
+    Market::with_options(vec![file.to_string()], options)
+}
+
+fn date(s: &str) -> chrono::NaiveDate {
+    s.parse().unwrap()
+}
+
+// A date at midnight, or a date and time such as 2022-01-03 09:30
+fn time(s: &str) -> chrono::NaiveDateTime {
+    chrono::NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M").unwrap_or_else(|_| date(s).into())
+}
+
+// Ensure every column of a CSV export is read
+#[test]
+fn market_csv() {
+    let m = market_with("testdata/csv/one-month-linear.csv", Options::default());
+    let history = &m.histories[0];
+
+    assert_eq!(history.len(), 31);
+    assert_eq!(history.times[0], time("2022-01-03"));
+    assert_eq!(history.times[30], time("2022-02-14"));
+    assert_eq!(history.open.len(), 31);
+    assert_eq!(history.high.len(), 31);
+    assert_eq!(history.low.len(), 31);
+    assert_eq!(history.volume[30], 31000.0);
+    assert!(history.gaps(4).is_empty());
+
+    for day in 0..history.len() {
+        assert_close(history.close[day], linear(day));
+        assert!(history.low[day] <= history.open[day] && history.open[day] <= history.high[day]);
+    }
+
+    // the same closing prices make the same trades as the plain-text file
+    let text = market("testdata/tests/one-month-linear.txt");
+    for g in [b"s005&e010|m002", b"e002|m003&s010"] {
+        assert_eq!(m.fitness(g), text.fitness(g));
+    }
+}
+
+// Ensure columns can be read under other names and date formats
+#[test]
+fn market_csv_columns() {
+    let m = market_with(
+        "testdata/csv/mapped.csv",
+        Options {
+            columns: vec!["date=timestamp".into(), "close=close_price".into()],
+            ..Default::default()
+        },
     );
+    let history = &m.histories[0];
+
+    assert_eq!(history.len(), 31);
+    assert_eq!(history.times[30], time("2022-02-14"));
+    assert!(history.open.is_empty());
+    assert!(history.volume.is_empty());
 
+    let m = market_with(
+        "testdata/csv/mapped.csv",
+        Options {
+            columns: vec![
+                "date=timestamp".into(),
+                "close=close_price".into(),
+                "volume=volume_traded".into(),
+            ],
+            ..Default::default()
+        },
+    );
+    assert_eq!(m.histories[0].volume.len(), 31);
 }
 
-// Ensure reproduction method (number of crossover points) is respected
 #[test]
-fn reproduce_uniform_crossover_point() {
-    let parent = (String::from("0123456789ABCDEF"), String::from("FEDCBA9876543210"));
-    let child = 
-        reproduce(
-            parent.clone(),
-            SexMethod::Uniform,
-            vec!['0', '1', '2', '3', '4', '5', '6', '7', '8', '9', 'A', 'B', 'C', 'D', 'E', 'F'],
-            0.0,
-            0.0,
-            false
-        );
+#[should_panic(expected = "no Date column")]
+fn market_csv_missing_column() {
+    market_with("testdata/csv/mapped.csv", Options::default());
+}
 
-    let mut test = child.0[0..1] == parent.0[0..1] && child.1[0..1] == parent.1[0..1];
+// Ensure days without prices are skipped and gaps are found
+#[test]
+fn market_csv_gaps() {
+    let m = market_with("testdata/csv/gap.csv", Options::default());
+    let history = &m.histories[0];
 
-    assert!(
-        child.0.chars().zip(child.1.chars())
-            .zip(parent.0.chars().zip(parent.1.chars()))
-            .fold(0, |num_points, ((c0, c1), (p0, p1))| {
-                if test == (c0 == p0 && c1 == p1) {
-                    num_points
-                } else {
-                    test = !test;
-                    num_points + 1
-                }
-            }
-        ) > 2
+    assert_eq!(history.len(), 30);
+    assert!(!history.times.contains(&time("2022-01-07")));
+    assert_eq!(
+        history.gaps(4),
+        [(date("2022-01-14"), date("2022-01-24"))]
     );
 }
 
-// generate_genitors(usize, usize, Vec<char>) -> Vec<String>
+#[test]
+#[should_panic(expected = "dates must be increasing")]
+fn market_csv_unordered() {
+    market_with("testdata/csv/unordered.csv", Options::default());
+}
 
-// Ensure the correct number of genitors are created
+// Ensure files without a single price are rejected instead of traded
 #[test]
-fn generate_genitors_correct_population() {
-    let population = rand::thread_rng().gen_range(10..200);
-    assert_eq!(population, generate_genitors(population, 10, vec!['A', 'B', 'C', 'D']).len());
+#[should_panic(expected = "testdata/csv/empty.csv contains no prices")]
+fn market_csv_empty() {
+    market_with("testdata/csv/empty.csv", Options::default());
 }
 
-// Ensure the genitors have the correct alphabet
+// Ensure intraday bars keep their times and windows count bars, not days
 #[test]
-fn generate_genitors_correct_alphabet() {
-    let population = rand::thread_rng().gen_range(10..100);
-    let alphabet = vec!['A', 'B', 'C', 'D'];
-    assert!(
-        generate_genitors(population, 10, alphabet.clone())
-            .iter()
-            .fold(true, |good, genitor| 
-                good && genitor
-                    .chars()
-                    .fold(true, |g, c| g && alphabet.contains(&c)
-                )
-            )
+fn market_intraday() {
+    let m = market_with("testdata/csv/intraday.csv", Options::default());
+    let history = &m.histories[0];
+
+    assert_eq!(history.len(), 78);
+    assert_eq!(history.times[1], time("2022-01-03 09:45"));
+    assert_eq!(history.range, Some((date("2022-01-03"), date("2022-01-05"))));
+    assert!(history.gaps(4).is_empty());
+    assert_eq!(history.year(), 252.0 * 26.0);
+
+    let bars = market_from(history.close.clone(), Options::default());
+    for g in [b"s010|s010|s010", b"e020&r514|m005"] {
+        assert_eq!(m.fitness(g), bars.fitness(g));
+    }
+
+    // splits can fall within a day
+    let m = market_with(
+        "testdata/csv/intraday.csv",
+        Options {
+            split: vec!["2022-01-04 12:00".into()],
+            ..Default::default()
+        },
     );
+    assert_eq!(m.histories[0].len(), 36);
+    assert_eq!(m.held_out[0].1.histories[0].start, 36);
 }
 
-// Ensure the genitors have the correct length
+// Ensure bars are combined into longer ones from their prices and volumes
 #[test]
-fn generate_genitors_correct_length() {
-    let population = rand::thread_rng().gen_range(10..100);
-    let length = 10;
-    assert!(
-        generate_genitors(population, length, vec!['A', 'B', 'C', 'D'])
-            .iter()
-            .fold(true, |good, genitor| good && genitor.len() == length)
+fn market_resample() {
+    let resampled = |frequency| {
+        let options = Options {
+            resample: Some(frequency),
+            ..Default::default()
+        };
+        market_with("testdata/csv/intraday.csv", options).histories.remove(0)
+    };
+
+    // from 09:30 and 09:45, then four bars each from 10:00 to 15:00
+    let hourly = resampled(Frequency::Hours(1));
+    assert_eq!(hourly.len(), 21);
+    assert_eq!(hourly.times[..2], [time("2022-01-03 09:00"), time("2022-01-03 10:00")]);
+    assert_eq!(hourly.times[7], time("2022-01-04 09:00"));
+    assert_eq!(
+        (hourly.open[0], hourly.high[0], hourly.low[0], hourly.close[0], hourly.volume[0]),
+        (0.5, 2.25, 0.25, 2.0, 200.0)
+    );
+    assert_eq!((hourly.open[1], hourly.close[1], hourly.volume[1]), (2.5, 6.0, 400.0));
+    assert_eq!(hourly.year(), 252.0 * 7.0);
+
+    let daily = resampled(Frequency::Days(1));
+    assert_eq!(daily.times, [time("2022-01-03"), time("2022-01-04"), time("2022-01-05")]);
+    assert_eq!(daily.close, [26.0, 52.0, 78.0]);
+    assert_eq!(daily.volume, [2600.0; 3]);
+    assert_eq!(daily.year(), 252.0);
+    assert_eq!(resampled(Frequency::Minutes(30)).len(), 39);
+
+    // 31 days from Monday 3 January to Monday 14 February
+    let options = Options {
+        resample: Some(Frequency::Weeks(1)),
+        ..Default::default()
+    };
+    let m = market_with("testdata/csv/one-month-linear.csv", options);
+    let weekly = &m.histories[0];
+    assert_eq!(weekly.len(), 7);
+    assert_eq!(weekly.times[1], time("2022-01-10"));
+    assert_close(weekly.close[0], linear(4));
+    assert_close(weekly.close[6], linear(30));
+    assert_close(weekly.year(), 365.25 / 7.0);
+}
+
+// Ensure frequencies are a number of minutes, hours, days or weeks
+#[test]
+fn market_frequency() {
+    for (s, frequency) in [
+        ("15m", Frequency::Minutes(15)),
+        ("1h", Frequency::Hours(1)),
+        ("2d", Frequency::Days(2)),
+        ("1w", Frequency::Weeks(1)),
+    ] {
+        assert_eq!(s.parse::<Frequency>(), Ok(frequency));
+    }
+
+    for s in ["0h", "h", "15", "1.5h", "1y", ""] {
+        assert!(s.parse::<Frequency>().is_err(), "{s}");
+    }
+
+    // 3 January 2022 was a Monday
+    let start = |frequency: Frequency, s| frequency.start(time(s));
+    assert_eq!(start(Frequency::Minutes(15), "2022-01-05 10:14"), time("2022-01-05 10:00"));
+    assert_eq!(start(Frequency::Hours(4), "2022-01-05 10:14"), time("2022-01-05 08:00"));
+    assert_eq!(start(Frequency::Days(1), "2022-01-05 10:14"), time("2022-01-05"));
+    assert_eq!(start(Frequency::Weeks(1), "2022-01-09 10:14"), time("2022-01-03"));
+}
+
+#[test]
+#[should_panic(expected = "no times to resample")]
+fn market_resample_without_times() {
+    let options = Options {
+        resample: Some(Frequency::Days(1)),
+        ..Default::default()
+    };
+    market_with("testdata/tests/one-month-linear.txt", options);
+}
+
+// Ensure trading at the next open uses the open prices
+#[test]
+fn market_trade_at_open() {
+    let options = Options {
+        trade_at_open: true,
+        ..Default::default()
+    };
+    let close = market_with("testdata/csv/one-month-linear.csv", Options::default());
+    let open = market_with("testdata/csv/one-month-linear.csv", options.clone());
+
+    // each day opens at the previous close, so the trades are the same
+    let g = b"s002&s002&s002";
+    assert_eq!(close.fitness(g), open.fitness(g));
+
+    let mut cheaper = open.histories[0].clone();
+    cheaper.open.iter_mut().for_each(|p| *p /= 2.0);
+    let cheaper = Market {
+        histories: vec![cheaper],
+        ..market_with("testdata/csv/one-month-linear.csv", options)
+    };
+    assert!(cheaper.fitness(g) > open.fitness(g));
+}
+
+#[test]
+#[should_panic(expected = "no open prices")]
+fn market_trade_at_open_without_prices() {
+    market_with(
+        "testdata/tests/one-month-linear.txt",
+        Options {
+            trade_at_open: true,
+            ..Default::default()
+        },
+    );
+}
+
+// Ensure the ticker and date range are read from the header
+#[test]
+fn market_header() {
+    let m = market("testdata/AAPL-1.txt");
+    let history = &m.histories[0];
+
+    assert_eq!(history.ticker, "AAPL");
+    assert_eq!(history.name, "Apple");
+    assert_eq!(
+        history.range,
+        Some((date("2000-01-01"), date("2002-12-31")))
+    );
+    assert_eq!(history.order, Order::OldestFirst);
+
+    let m = market("testdata/final-test/AMZN-1_month.txt");
+    assert_eq!(
+        m.histories[0].range,
+        Some((date("2006-01-01"), date("2006-01-31")))
     );
+
+    // CSV exports are named after their file
+    let m = market_with("testdata/csv/one-month-linear.csv", Options::default());
+    assert_eq!(m.histories[0].ticker, "one-month-linear");
+    assert_eq!(
+        m.histories[0].range,
+        Some((date("2022-01-03"), date("2022-02-14")))
+    );
+}
+
+// Ensure histories are normalized to chronological order
+#[test]
+fn market_order() {
+    // the range is written newest first, but the prices are oldest first
+    let m = market("testdata/mine/AAPL.txt");
+    let history = &m.histories[0];
+
+    assert_eq!(
+        history.range,
+        Some((date("2013-03-27"), date("2023-03-24")))
+    );
+    assert_eq!(history.order, Order::OldestFirst);
+    assert_close(history.close[0], 16.14);
+
+    let m = market_with(
+        "testdata/mine/AAPL.txt",
+        Options {
+            order: Some(Order::NewestFirst),
+            ..Default::default()
+        },
+    );
+    let reversed = &m.histories[0];
+
+    assert_eq!(reversed.order, Order::NewestFirst);
+    assert_close(reversed.close[0], 160.25);
+    assert!(reversed.close.iter().eq(history.close.iter().rev()));
+
+    // CSV dates decide the order
+    let oldest = market_with("testdata/csv/one-month-linear.csv", Options::default());
+    let newest = market_with("testdata/csv/newest-first.csv", Options::default());
+
+    assert_eq!(newest.histories[0].order, Order::NewestFirst);
+    assert_eq!(newest.histories[0].times, oldest.histories[0].times);
+    assert_eq!(newest.histories[0].open, oldest.histories[0].open);
+    assert_eq!(newest.histories[0].close, oldest.histories[0].close);
+    assert_eq!(newest.histories[0].volume, oldest.histories[0].volume);
 }
 
-// generate_population(impl Fn(String) -> f64, Option<Vec<String>>, usize, usize, Vec<char>, SelectionMethod) -> Vec<String>
+// Ensure results are reported for each stock
+#[test]
+fn market_returns() {
+    let m = Market::new(vec![
+        "testdata/AAPL-1.txt".to_string(),
+        "testdata/DDS-1.txt".to_string(),
+    ])
+    .unwrap();
+    let g = Genotype::from(b"e010|m002|s101".to_vec(), Fitness::Invalid);
+    let returns = m.returns(&g.genotype).unwrap();
+
+    assert_eq!(returns.len(), 2);
+    assert_close(returns.iter().sum(), m.fitness(&g.genotype).unwrap());
+
+    let s = m.format(&g);
+    assert!(s.contains(&format!("AAPL ${:.2}", returns[0])), "{s}");
+    assert!(s.contains(&format!("DDS ${:.2}", returns[1])), "{s}");
+}
+
+fn market_from(close: Vec<f64>, options: Options) -> Market {
+    Market {
+        histories: vec![History {
+            ticker: "TEST".to_string(),
+            close,
+            ..Default::default()
+        }],
+        options,
+        ..Market::new(Vec::new()).unwrap()
+    }
+}
+
+// Ensure commission, fees and slippage are charged on each trade.
+// s001 buys at 11 on the second day and can't afford to buy again at 12,
+// then sells everything at 13 on the last day.
+#[test]
+fn market_transaction_costs() {
+    let prices = vec![10.0, 11.0, 12.0, 13.0];
+    let g = b"s001|s001|s001";
+
+    let free = market_from(prices.clone(), Options::default());
+    assert_eq!(free.fitness(g), Fitness::Valid(1818.0 * 13.0 + 2.0 - 20000.0));
+
+    let options = Options {
+        commission: 10.0,
+        fee: 0.001,
+        slippage: 0.002,
+        ..Default::default()
+    };
+    let cost: f64 = 11.0 * 1.002 * 1.001;
+    let shares = ((20000.0 - 10.0) / cost).floor();
+    let spent = shares * cost + 10.0;
+    let earned = shares * 13.0 * 0.998 * 0.999 - 10.0;
+
+    assert_eq!(shares, 1811.0);
+    assert_close(
+        market_from(prices.clone(), options.clone()).fitness(g).unwrap(),
+        earned - spent,
+    );
+
+    // every share bought is sold, so no money is left over
+    let fractional = Options {
+        fractional: true,
+        ..options
+    };
+    let shares = (20000.0 - 10.0) / cost;
+    assert_close(
+        market_from(prices, fractional).fitness(g).unwrap(),
+        shares * 13.0 * 0.998 * 0.999 - 10.0 - 20000.0,
+    );
+}
+
+// Ensure costs penalize strategies that trade every day more than those that don't
+#[test]
+fn market_transaction_costs_trading_often() {
+    let options = Options {
+        commission: 5.0,
+        fee: 0.001,
+        ..Default::default()
+    };
+    let free = market("testdata/AAPL-1.txt");
+    let costly = Market {
+        options,
+        ..market("testdata/AAPL-1.txt")
+    };
+
+    let loss = |g: &[u8]| free.fitness(g).unwrap() - costly.fitness(g).unwrap();
+
+    assert!(loss(b"m002|m002|m002") > 0.0);
+    assert!(loss(b"m002|m002|m002") > loss(b"s100&s100&s100"));
+}
+
+// Ensure the equity curve covers every day and ends with the profit made
+#[test]
+fn market_equity() {
+    let m = market("testdata/AAPL-1.txt");
+
+    for g in [b"e010|m002|s101", b"s000|s000|s000", b"s100&s100&s100"] {
+        let simulation = &m.simulate(g).unwrap()[0];
+
+        assert_eq!(simulation.equity.len(), m.histories[0].len());
+        assert_eq!(simulation.equity[0], m.funds);
+        assert_close(*simulation.equity.last().unwrap() - m.funds, simulation.profit);
+    }
+}
+
+// Ensure each objective measures the equity curve as expected
+#[test]
+fn market_objectives() {
+    let simulation = Simulation {
+        profit: 20.0,
+        trades: 4,
+        equity: vec![100.0, 110.0, 99.0, 120.0],
+        ledger: Vec::new(),
+        year: 252.0,
+    };
+    let returns = [0.1, -0.1, 120.0 / 99.0 - 1.0];
+    let mean = returns.iter().sum::<f64>() / 3.0;
+    let deviation = (returns.iter().map(|r| (r - mean).powi(2)).sum::<f64>() / 3.0).sqrt();
+    let downside = (0.01f64 / 3.0).sqrt();
+    let cagr = 1.2f64.powf(252.0 / 3.0) - 1.0;
+
+    simulation
+        .daily_returns()
+        .iter()
+        .zip(returns)
+        .for_each(|(a, b)| assert_close(*a, b));
+    assert_close(simulation.max_drawdown(), 0.1);
+    assert_close(simulation.cagr(), cagr);
+    assert_close(simulation.sharpe(), mean / deviation * 252f64.sqrt());
+    assert_close(simulation.sortino(), mean / downside * 252f64.sqrt());
+    assert_close(simulation.calmar(), cagr / 0.1);
+
+    let simulations = [simulation.clone(), simulation];
+    assert_eq!(Objective::TotalReturn.score(&simulations), 40.0);
+    assert_eq!(Objective::ProfitPerTrade.score(&simulations), 5.0);
+    assert_close(Objective::MaxDrawdown.score(&simulations), -0.1);
+    assert_close(Objective::Cagr.score(&simulations), cagr);
+
+    // a strategy that never trades has no risk or return
+    let idle = Simulation {
+        profit: 0.0,
+        trades: 0,
+        equity: vec![100.0; 10],
+        ledger: Vec::new(),
+        year: 252.0,
+    };
+    for objective in Objective::value_variants() {
+        assert_eq!(objective.score(std::slice::from_ref(&idle)), 0.0);
+    }
+}
+
+// Ensure the objective decides the fitness
+#[test]
+fn market_objective_fitness() {
+    let prices = vec![10.0, 11.0, 12.0, 13.0];
+    let g = b"s001|s001|s001";
+    let profit = 1818.0 * 13.0 + 2.0 - 20000.0;
+
+    let m = market_from(prices.clone(), Options::default());
+    assert_eq!(m.fitness(g), Fitness::Valid(profit));
+
+    let m = market_from(
+        prices,
+        Options {
+            objective: Objective::ProfitPerTrade,
+            ..Default::default()
+        },
+    );
+    assert_eq!(m.simulate(g).unwrap()[0].trades, 2);
+    assert_eq!(m.fitness(g), Fitness::Valid(profit / 2.0));
+    assert!(m
+        .format(&Genotype::from(g.to_vec(), Fitness::Invalid))
+        .starts_with(&format!(
+            "s001|s001|s001 made ${profit:.2} (TEST ${profit:.2}) with a profit per trade of {:.4} vs",
+            profit / 2.0
+        )));
+}
+
+// Ensure the objective on each ticker can be combined so no one ticker decides
+// the fitness
+#[test]
+fn market_aggregate() {
+    let files = ["testdata/AAPL-1.txt", "testdata/DDS-1.txt", "testdata/AAPL-3.txt"];
+    let files: Vec<String> = files.iter().map(|f| f.to_string()).collect();
+    let aggregated = |aggregate, objective| {
+        let options = Options {
+            aggregate: Some(aggregate),
+            objective,
+            ..Default::default()
+        };
+        Market::with_options(files.clone(), options)
+    };
+    let g = b"s020&r714|e010";
+
+    let m = Market::new(files.clone()).unwrap();
+    let mut returns = m.returns(g).unwrap();
+    let held: Vec<f64> = m.buy_and_hold().iter().map(|s| s.profit).collect();
+    let beaten = returns.iter().zip(&held).filter(|(r, h)| r > h).count();
+    let total = returns.iter().sum::<f64>();
+    returns.sort_by(f64::total_cmp);
+
+    let fitness = |aggregate, objective| match aggregated(aggregate, objective).fitness(g) {
+        Fitness::Valid(f) => f,
+        Fitness::Invalid => panic!("Invalid fitness"),
+    };
+    assert_close(fitness(Aggregate::Mean, Objective::TotalReturn), total / 3.0);
+    assert_eq!(fitness(Aggregate::Median, Objective::TotalReturn), returns[1]);
+    assert_eq!(fitness(Aggregate::Worst, Objective::TotalReturn), returns[0]);
+    assert_eq!(fitness(Aggregate::BeatHold, Objective::TotalReturn), beaten as f64 / 3.0);
+
+    let sharpe =
+        m.simulate(g).unwrap().iter().map(|s| Objective::Sharpe.measure(s)).collect::<Vec<_>>();
+    assert_eq!(fitness(Aggregate::Worst, Objective::Sharpe), Aggregate::Worst.combine(&sharpe));
+
+    // the report breaks the fitness down by ticker
+    let report = aggregated(Aggregate::Median, Objective::TotalReturn)
+        .format(&Genotype::from(g.to_vec(), Fitness::Invalid));
+    assert!(report.contains(&format!("with a median total return of {:.4} (AAPL ", returns[1])));
+    let report = aggregated(Aggregate::BeatHold, Objective::TotalReturn)
+        .format(&Genotype::from(g.to_vec(), Fitness::Invalid));
+    let beating = format!("beating buying and holding on {beaten} of 3 by total return");
+    assert!(report.contains(&beating));
+    assert!(beaten > 0 && beaten < 3);
+
+    assert_eq!(Aggregate::Median.combine(&[3.0, 1.0, 4.0, 2.0]), 2.5);
+    assert_eq!(Aggregate::Worst.combine(&[3.0, -1.0, 4.0]), -1.0);
+    assert_eq!(Aggregate::Mean.combine(&[]), 0.0);
+}
+
+// Ensure a block bootstrap redraws the strategy's daily returns, and one block as
+// long as the history draws exactly what it made
+#[test]
+fn market_bootstrap_blocks() {
+    let files = vec!["testdata/AAPL-1.txt".to_string(), "testdata/DDS-1.txt".to_string()];
+    let g = b"s020&r714|e010";
+    let bootstrap = |block| {
+        let options = Options {
+            block,
+            ..Default::default()
+        };
+        Market::with_options(files.clone(), options)
+    };
+
+    let m = bootstrap(10000);
+    let results = m.bootstrap(g, 5, 7).unwrap();
+    let simulations = m.simulate(g).unwrap();
+    let made = simulations.iter().map(|s| s.profit).sum::<f64>();
+    let held = m.buy_and_hold().iter().map(|s| s.profit).sum::<f64>();
+    let drawdown = simulations.iter().map(|s| s.max_drawdown()).sum::<f64>() / 2.0;
+
+    for sample in 0..5 {
+        assert!((results.returns[sample] - made).abs() < 1e-6);
+        assert!((results.drawdowns[sample] - drawdown).abs() < 1e-9);
+        assert_eq!(results.beaten[sample], made > held);
+    }
+
+    // the same seed draws the same samples on any thread
+    let m = bootstrap(20);
+    let results = m.bootstrap(g, 200, 7).unwrap();
+    assert_eq!(results.returns, m.bootstrap(g, 200, 7).unwrap().returns);
+    assert_ne!(results.returns, m.bootstrap(g, 200, 8).unwrap().returns);
+    assert_eq!(results.drawdowns.len(), 200);
+
+    let (low, high) = Bootstrap::interval(&results.returns, 0.9);
+    assert!(low < made && made < high, "{low} {made} {high}");
+    assert!(results.drawdowns.iter().all(|d| (0.0..=1.0).contains(d)));
+    assert!(results.describe(0.9).starts_with("90% of 200 samples made $"));
+}
+
+// Ensure random starts trade from a day in the first half of each history
+#[test]
+fn market_bootstrap_starts() {
+    let options = Options {
+        resampling: Resampling::Starts,
+        ..Default::default()
+    };
+    let m = market_with("testdata/tests/one-year-sinusoidal.txt", options);
+    let g = b"s005&e010|m002";
+    let history = &m.histories[0];
+
+    // what trading from each day in the first half makes
+    let made: Vec<f64> = (0..=(history.len() - 1) / 2)
+        .map(|first| {
+            let market = Market {
+                histories: vec![history.segment(first, history.len())],
+                options: m.options.clone(),
+                ..Market::new(Vec::new()).unwrap()
+            };
+            market.returns(g).unwrap()[0]
+        })
+        .collect();
+
+    let results = m.bootstrap(g, 50, 1).unwrap();
+    assert!(results.returns.iter().all(|r| made.contains(r)));
+    assert!(results.returns.iter().any(|r| *r != results.returns[0]));
+}
+
+// Ensure confidence intervals cover the middle of the samples
+#[test]
+fn bootstrap_interval() {
+    let values: Vec<f64> = (1..=100).rev().map(f64::from).collect();
+
+    assert_eq!(Bootstrap::interval(&values, 0.9), (6.0, 95.0));
+    assert_eq!(Bootstrap::interval(&values, 1.0), (1.0, 100.0));
+    assert_eq!(Bootstrap::interval(&values, 0.0), (51.0, 51.0));
+    assert_eq!(Bootstrap::interval(&[], 0.9), (0.0, 0.0));
+
+    let results = Bootstrap {
+        beaten: vec![true, false, true, true],
+        ..Default::default()
+    };
+    assert_eq!(results.beat_chance(), 0.75);
+}
+
+fn split(file: &str, split: &[&str]) -> Market {
+    market_with(
+        file,
+        Options {
+            split: split.iter().map(|s| s.to_string()).collect(),
+            ..Default::default()
+        },
+    )
+}
+
+// Ensure held-out days come after the training days and keep the days before
+// them for indicators
+#[test]
+fn market_split_fraction() {
+    let full = market("testdata/AAPL-1.txt");
+    let days = full.histories[0].len();
+    let half = (days as f64 / 2.0).round() as usize;
+    let m = split("testdata/AAPL-1.txt", &["0.5"]);
+
+    assert_eq!(m.histories[0].len(), half);
+    assert_eq!(m.histories[0].start, 0);
+    assert_eq!(m.held_out.len(), 1);
+
+    let (name, test) = &m.held_out[0];
+    assert_eq!(name, "test");
+    assert_eq!(test.histories[0].start, half);
+    assert_eq!(test.histories[0].close, full.histories[0].close);
+
+    // only the held-out days are traded
+    let g = b"e010|m002|s101";
+    assert_eq!(test.simulate(g).unwrap()[0].equity.len(), days - half);
+    assert!(m.format(&Genotype::from(g.to_vec(), Fitness::Invalid))
+        .contains(" in sample | "));
+}
+
+#[test]
+fn market_split_dates() {
+    let m = split("testdata/csv/one-month-linear.csv", &["2022-01-17", "2022-02-01"]);
+    let names: Vec<&str> = m.held_out.iter().map(|(n, _)| n.as_str()).collect();
+
+    assert_eq!(names, ["validation", "test"]);
+    assert_eq!(m.histories[0].len(), 10);
+    assert_eq!(
+        m.histories[0].range,
+        Some((date("2022-01-03"), date("2022-01-14")))
+    );
+
+    let validation = &m.held_out[0].1.histories[0];
+    assert_eq!((validation.start, validation.len()), (10, 21));
+    assert_eq!(
+        validation.range,
+        Some((date("2022-01-17"), date("2022-01-31")))
+    );
+
+    let test = &m.held_out[1].1.histories[0];
+    assert_eq!((test.start, test.len()), (21, 31));
+    assert_eq!(
+        test.range,
+        Some((date("2022-02-01"), date("2022-02-14")))
+    );
+}
+
+#[test]
+#[should_panic(expected = "no dates")]
+fn market_split_dates_without_dates() {
+    split("testdata/AAPL-1.txt", &["2001-01-01"]);
+}
+
+#[test]
+#[should_panic(expected = "every part needs at least one day")]
+fn market_split_empty() {
+    split("testdata/AAPL-1.txt", &["0.5", "0.5"]);
+}
+
+// Ensure strategies are tested on held-out tickers
+#[test]
+fn market_test_file() {
+    let m = market_with(
+        "testdata/AAPL-1.txt",
+        Options {
+            test_file: vec!["testdata/GE-1.txt".to_string()],
+            ..Default::default()
+        },
+    );
+    let (name, held_out) = &m.held_out[0];
+
+    assert_eq!(name, "held-out");
+    assert_eq!(held_out.histories[0].ticker, "GE");
+    assert!(held_out.held_out.is_empty());
+
+    let s = m.format(&Genotype::from(b"e010|m002|s101".to_vec(), Fitness::Invalid));
+    assert!(s.contains("(GE $") && s.ends_with("on held-out"), "{s}");
+}
+
+// Ensure walk-forward windows roll forward by the test days
+#[test]
+fn market_walk_forward() {
+    let m = market_with(
+        "testdata/AAPL-1.txt",
+        Options {
+            walk_forward: vec![250, 125],
+            ..Default::default()
+        },
+    );
+    let days = m.histories[0].len();
+    let windows = m.windows();
+
+    assert_eq!(windows.len(), (days - 375) / 125 + 1);
+
+    for (w, (train, test)) in windows.iter().enumerate() {
+        let (train, test) = (&train.histories[0], &test.histories[0]);
+
+        assert_eq!((train.start, train.len()), (w * 125, w * 125 + 250));
+        assert_eq!((test.start, test.len()), (w * 125 + 250, w * 125 + 375));
+    }
+}
+
+// Ensure buying and holding is hard to beat when prices only rise
+#[test]
+fn market_buy_and_hold() {
+    let m = market("testdata/tests/one-year-linear.txt");
+    let held = &m.buy_and_hold()[0];
+
+    // every dollar buys shares at the lowest price, which are sold at the highest
+    assert_eq!(held.trades, 2);
+    assert_close(held.profit, 20000.0 / linear(0) * linear(355) - 20000.0);
+
+    for g in [
+        b"e010|m002|s101",
+        b"s001|s001|s001",
+        b"m100&e050|s020",
+        b"s005&s010&s020",
+    ] {
+        assert!(m.simulate(g).unwrap()[0].profit <= held.profit);
+    }
+
+    // held-out days are bought when they start
+    let m = split("testdata/tests/one-year-linear.txt", &["0.5"]);
+    let test = &m.held_out[0].1;
+    let start = test.histories[0].start;
+    let shares = (20000.0 / linear(start)).floor();
+
+    assert_close(
+        test.buy_and_hold()[0].profit,
+        shares * linear(355) - shares * linear(start),
+    );
+}
+
+// Ensure the crossover benchmark holds while the short average is above the long one
+#[test]
+fn market_sma_crossover() {
+    let m = market("testdata/tests/one-year-linear.txt");
+    let crossover = &m.sma_crossover()[0];
+    let shares = (20000.0 / linear(200)).floor();
+
+    // rising prices keep the short average above the long one from day 200
+    assert_eq!(crossover.trades, 2);
+    assert_close(crossover.profit, shares * (linear(355) - linear(200)));
+
+    // too short for a 200 day average
+    let m = market("testdata/tests/one-month-linear.txt");
+    assert_eq!(m.sma_crossover()[0].trades, 0);
+
+    let s = m.format(&Genotype::from(b"e010|m002|s101".to_vec(), Fitness::Invalid));
+    assert!(s.contains("buying and holding vs $0.00 (TEST $0.00) on a 50/200 day SMA"));
+}
+
+// Ensure every trade is recorded with what was left after it
+#[test]
+fn market_ledger() {
+    let m = market_from(vec![10.0, 11.0, 12.0, 13.0], Options::default());
+    let g = b"s001|s001|s001";
+    let simulation = &m.ledger(g).unwrap()[0];
+
+    assert!(m.simulate(g).unwrap()[0].ledger.is_empty());
+    assert_eq!(simulation.ledger.len(), simulation.trades);
+    assert_eq!(
+        simulation.ledger,
+        [
+            Trade {
+                day: 1,
+                buy: true,
+                price: 11.0,
+                shares: 1818.0,
+                capital: 2.0,
+                gains: 0.0,
+            },
+            Trade {
+                day: 3,
+                buy: false,
+                price: 13.0,
+                shares: 1818.0,
+                capital: 2.0,
+                gains: 1818.0 * 13.0,
+            },
+        ]
+    );
+}
+
+// Ensure the ledger and equity curve are written for every sample
+#[test]
+fn market_write_trades() {
+    let m = split("testdata/csv/one-month-linear.csv", &["0.5"]);
+    let g: &[u8] = b"s002|s003&m005";
+    let name = format!("genetic-trades-{}", std::process::id());
+    let file = std::env::temp_dir().join(format!("{name}.csv"));
+    let equity_file = std::env::temp_dir().join(format!("{name}-equity.csv"));
+
+    let samples: Vec<(&str, &Market, &[u8])> =
+        m.samples().into_iter().map(|(name, m)| (name, m, g)).collect();
+    Market::write_trades(file.to_str().unwrap(), &samples).unwrap();
+
+    let trades = std::fs::read_to_string(&file).unwrap();
+    let equity = std::fs::read_to_string(&equity_file).unwrap();
+    std::fs::remove_file(&file).unwrap();
+    std::fs::remove_file(&equity_file).unwrap();
+
+    let trades: Vec<&str> = trades.lines().collect();
+    let count = m.simulate(g).unwrap()[0].trades + m.held_out[0].1.simulate(g).unwrap()[0].trades;
+
+    assert_eq!(trades[0], "sample,ticker,day,date,action,price,shares,capital,gains");
+    assert_eq!(trades.len(), count + 1);
+    assert!(trades[1].starts_with("in-sample,one-month-linear,2,2022-01-05,buy,0.03,666666,"));
+
+    // one row a day, with the test days after the training ones
+    let equity: Vec<&str> = equity.lines().collect();
+    assert_eq!(equity[0], "sample,ticker,day,date,equity");
+    assert_eq!(equity.len(), 32);
+    assert_eq!(equity[1], "in-sample,one-month-linear,0,2022-01-03,20000");
+    assert!(equity[17].starts_with("test,one-month-linear,16,2022-01-25,"));
+}
+
+// Ensure each gene letter is read as its indicator and parameters
+#[test]
+fn market_parse_indicators() {
+    let days = |gene: &[u8; 4]| Market::parse(*gene).unwrap().days();
+
+    assert!(matches!(Market::parse(*b"n123").unwrap(), Indicator::Average(Average::Minimum(123))));
+    assert!(matches!(Market::parse(*b"w010").unwrap(), Indicator::Average(Average::Weighted(10))));
+    assert!(matches!(Market::parse(*b"c026").unwrap(), Indicator::Macd(26)));
+    assert!(matches!(
+        Market::parse(*b"r714").unwrap(),
+        Indicator::Rsi { days: 14, level } if level == 70.0
+    ));
+    assert!(matches!(
+        Market::parse(*b"b120").unwrap(),
+        Indicator::Bollinger { days: 20, deviations } if deviations == -2.0
+    ));
+    assert!(matches!(
+        Market::parse(*b"o710").unwrap(),
+        Indicator::RateOfChange { days: 10, percent } if percent == 2.0
+    ));
+    assert!(matches!(
+        Market::parse(*b"v720").unwrap(),
+        Indicator::Volume { days: 20, ratio } if ratio == 1.4
+    ));
+
+    // the slow EMA and then the signal line need their days
+    assert_eq!(days(b"c026"), 26 + 8);
+    assert_eq!(days(b"c001"), 0);
+    assert_eq!(days(b"r714"), 14);
+}
+
+// Ensure invalid genes are reported instead of panicking
+#[test]
+fn market_parse_invalid_indicator() {
+    assert_eq!(
+        Market::parse(*b"x010").unwrap_err(),
+        "unknown indicator x in gene x010, expected s, e, w, m, n, r, c, b, o or v"
+    );
+    assert_eq!(
+        Market::parse(*b"s0a0").unwrap_err(),
+        "gene s0a0 needs three digits after its letter"
+    );
+}
+
+// Ensure the oscillators match their definitions on simple prices
+#[test]
+fn market_oscillators() {
+    let m = market("testdata/tests/one-year-linear.txt");
+    let history = &m.histories[0];
+    let value = |gene: &[u8; 4], day| Market::parse(*gene).unwrap().value(history, day);
+
+    for day in 14..history.len() {
+        // the price only rises and is always above its mean
+        assert_eq!(value(b"r014", day), Some(100.0));
+        assert_close(
+            value(b"o010", day).unwrap(),
+            (linear(day) / linear(day - 10) - 1.0) * 100.0,
+        );
+
+        // the last of n evenly spaced prices is sqrt(3(n-1)/(n+1)) deviations above the mean
+        assert_close(value(b"b010", day).unwrap(), (3.0 * 9.0 / 11.0_f64).sqrt());
+    }
+
+    // no values until there are enough days
+    assert_eq!(value(b"r014", 13), None);
+    assert_eq!(value(b"o010", 9), None);
+    assert_eq!(value(b"b010", 8), None);
+
+    // a flat price has an RSI in the middle and no deviation
+    let m = market_from(vec![1.0; 20], Options::default());
+    let history = &m.histories[0];
+    assert_eq!(Market::parse(*b"r010").unwrap().value(history, 15), Some(50.0));
+    assert_eq!(Market::parse(*b"b010").unwrap().value(history, 15), Some(0.0));
+
+    let m = market("testdata/tests/one-year-sinusoidal.txt");
+    let history = &m.histories[0];
+
+    for day in 20..history.len() {
+        let changes: Vec<f64> = (day - 14..day)
+            .map(|d| sinusoidal(d + 1) - sinusoidal(d))
+            .collect();
+        let gains: f64 = changes.iter().filter(|c| **c > 0.0).sum();
+        let losses: f64 = -changes.iter().filter(|c| **c < 0.0).sum::<f64>();
+
+        assert_close(
+            Market::parse(*b"r014").unwrap().value(history, day).unwrap(),
+            100.0 * gains / (gains + losses),
+        );
+    }
+}
+
+// Ensure the MACD updated day by day matches computing it from scratch, and
+// is positive while the price is rising faster than before
+#[test]
+fn market_macd() {
+    let m = market("testdata/tests/one-year-sinusoidal.txt");
+    let history = &m.histories[0];
+    let macd = Market::parse(*b"c026").unwrap();
+
+    let (mut fast, mut slow, mut signal) = (sinusoidal(0), sinusoidal(0), 0.0);
+    let (a, b, c) = (2.0 / 14.0, 2.0 / 27.0, 2.0 / 9.0);
+
+    for day in 1..history.len() {
+        fast += a * (sinusoidal(day) - fast);
+        slow += b * (sinusoidal(day) - slow);
+        signal += c * (fast - slow - signal);
+
+        if day < macd.days() {
+            assert_eq!(macd.value(history, day), None);
+        } else {
+            assert_close(macd.value(history, day).unwrap(), fast - slow - signal);
+        }
+    }
+
+    let m = market_from((0..60).map(|d| (d * d) as f64).collect(), Options::default());
+    assert!(macd.value(&m.histories[0], 59).unwrap() > 0.0);
+}
+
+// Ensure volume is compared with its average over the days before
+#[test]
+fn market_volume() {
+    let m = market_with("testdata/csv/one-month-linear.csv", Options::default());
+    let history = &m.histories[0];
+
+    // volume is 1000, 2000, 3000, ...
+    for day in 5..history.len() {
+        let average = (day - 5..day).map(|d| 1000.0 * (d + 1) as f64).sum::<f64>() / 5.0;
+        assert_close(
+            Market::parse(*b"v005").unwrap().value(history, day).unwrap(),
+            1000.0 * (day + 1) as f64 / average,
+        );
+    }
+
+    // without volume the gene never has a value
+    let m = market("testdata/tests/one-year-linear.txt");
+    assert_eq!(Market::parse(*b"v005").unwrap().value(&m.histories[0], 20), None);
+    assert!(!m.methods().contains(&b'v'));
+    assert_eq!(m.simulate(b"v505|v505|v505").unwrap()[0].trades, 0);
+}
+
+// Ensure new genotypes only use indicators the histories can provide, with
+// parameters that can be read
+#[test]
+fn market_generate_indicators() {
+    for (m, volume) in [
+        (market("testdata/tests/one-year-linear.txt"), false),
+        (market_with("testdata/csv/one-month-linear.csv", Options::default()), true),
+    ] {
+        let mut seen = Vec::new();
+
+        for _ in 0..500 {
+            let g = m.generate_genotype(false);
+            assert_eq!(g.genotype.len(), m.len());
+
+            for gene in g.genotype.chunks(5) {
+                assert!(m.methods().contains(&gene[0]), "{}", g);
+                Market::parse(gene[0..4].try_into().unwrap()).unwrap();
+                seen.push(gene[0]);
+            }
+        }
+
+        assert_eq!(seen.contains(&b'v'), volume);
+        assert!(b"semnwrcbo".iter().all(|k| seen.contains(k)));
+    }
+}
+
+// Ensure the original moving average genes still trade exactly as before, apart
+// from exponential ones, which used to drop the oldest price from their window
+// with the weight of a price a day older
+#[test]
+fn market_average_regression() {
+    let m = Market::new(vec![
+        "testdata/AAPL-1.txt".to_string(),
+        "testdata/DDS-1.txt".to_string(),
+        "testdata/mine/TSLA.txt".to_string(),
+    ])
+    .unwrap();
+
+    for (g, made) in [
+        ("e010|m002|s101", "483044.90"),
+        ("s005&e010|m002", "82819.74"),
+        ("m106&e004|s002", "43939.44"),
+        ("e107&e109|m016", "55783.77"),
+        ("s000|e000&m000", "0.00"),
+        ("s150|m020|e030", "135511.72"),
+        ("m100&s200&e050", "43309.03"),
+        ("s005&s010|m002", "101475.02"),
+        ("s150|m020|s030", "160991.36"),
+    ] {
+        let returns: f64 = m.returns(g.as_bytes()).unwrap().iter().sum();
+        assert_eq!(format!("{returns:.2}"), made, "{g}");
+    }
+}
+
+fn rules(options: Options) -> Market {
+    market_with(
+        "testdata/tests/one-year-sinusoidal.txt",
+        Options {
+            rules: true,
+            ..options
+        },
+    )
+}
+
+// Ensure rules are written as readable text that parses back into the same rule
+#[test]
+fn rule_text() {
+    let text = "((close > sma(50) | ema(10) < wma(20)) & !(rsi(14) > 70.5))";
+    let rule = Rule::parse(text).unwrap();
+
+    assert_eq!(rule.to_string(), text);
+    assert_eq!(rule.depth(), 3);
+    assert_eq!(rule.size(), 12);
+    assert!(matches!(
+        rule,
+        Rule::And(_, ref b) if **b == Rule::Not(Box::new(Rule::Above(
+            Value::Indicator(Indicator::Rsi { days: 14, level: 0.0 }),
+            Value::Constant(70.5),
+        )))
+    ));
+
+    // spaces and redundant parentheses don't matter
+    assert_eq!(
+        Rule::parse("(( close>sma(50) ) | !!high(3)<low(4))").unwrap().to_string(),
+        "(close > sma(50) | !!(high(3) < low(4)))"
+    );
+
+    let mut rng = rand::thread_rng();
+    let methods = b"semnwrcbov";
+
+    for _ in 0..200 {
+        let rule = Rule::random(5, methods, &mut rng);
+        assert!(rule.depth() <= 5);
+        assert_eq!(Rule::parse(&rule.to_string()), Ok(rule));
+    }
+
+    for bad in ["", "close", "close >", "close > foo(3)", "(close > sma(3)", "close > sma(3))"] {
+        assert!(Rule::parse(bad).is_err(), "{bad}");
+    }
+}
+
+// Ensure a rule trades like the genes it's equivalent to
+#[test]
+fn rule_signals() {
+    let m = rules(Options::default());
+    let genes = market("testdata/tests/one-year-sinusoidal.txt");
+
+    for (rule, g) in [
+        ("sma(5) < close", "s005|s005|s005"),
+        ("(close > ema(20) | rsi(14) > 60)", "e020|r614|r614"),
+    ] {
+        assert_eq!(
+            m.returns(rule.as_bytes()).unwrap(),
+            genes.returns(g.as_bytes()).unwrap(),
+            "{rule}"
+        );
+    }
+
+    // comparisons without values yet are false
+    let history = &m.histories[0];
+    let signals = Rule::parse("!(close > sma(10))").unwrap().signals(&m, 0);
+    assert!(signals[..10].iter().all(|s| *s));
+    assert_eq!(signals.len(), history.len());
+}
+
+// Ensure larger rules are penalized and invalid ones aren't fit
+#[test]
+fn rule_parsimony() {
+    let small = b"close > sma(10)";
+    let large = b"(close > sma(10) | close > sma(10))";
+
+    let m = rules(Options::default());
+    assert_eq!(m.fitness(small), m.fitness(large));
+    assert_eq!(m.fitness(b"close > "), Fitness::Invalid);
+
+    let p = rules(Options {
+        parsimony: 2.0,
+        ..Default::default()
+    });
+    assert_eq!(p.fitness(small).unwrap(), m.fitness(small).unwrap() - 6.0);
+    assert_eq!(p.fitness(large).unwrap(), m.fitness(large).unwrap() - 14.0);
+}
+
+// Ensure crossover swaps subtrees without going deeper than the limit, and
+// mutation always changes a rule when it's forced to
+#[test]
+fn rule_crossover_and_mutation() {
+    let m = rules(Options {
+        max_depth: 3,
+        ..Default::default()
+    });
+    let mut rng = rand::thread_rng();
+    let methods = m.methods();
+
+    for _ in 0..200 {
+        let (a, b) = (Rule::random(3, &methods, &mut rng), Rule::random(3, &methods, &mut rng));
+        let (mut x, mut y) = (a.clone(), b.clone());
+        Rule::crossover(&mut x, &mut y, 3, &mut rng);
+
+        assert!(x.depth() <= 3 && y.depth() <= 3);
+
+        // unless a child was too deep, the rules were only moved between them
+        if x != a && y != b {
+            assert_eq!(x.size() + y.size(), a.size() + b.size());
+        }
+
+        let mut g = Genotype::from(a.to_string().into_bytes(), Fitness::Invalid);
+        m.mutate(0.0, true, &mut g);
+
+        let mutated = Rule::parse(std::str::from_utf8(&g.genotype).unwrap()).unwrap();
+        assert_ne!(mutated, a);
+        assert!(mutated.depth() <= 3);
+        assert_eq!(g.fitness, m.fitness(&g.genotype));
+    }
+
+    let (mut x, mut y) = (
+        genotype("close > sma(10)"),
+        genotype("(rsi(14) > 70 & !(close < low(5)))"),
+    );
+    m.crossover(&mut x, &mut y);
+    assert!(Rule::parse(std::str::from_utf8(&x.genotype).unwrap()).is_ok());
+    assert!(Rule::parse(std::str::from_utf8(&y.genotype).unwrap()).is_ok());
+    assert_eq!(m.len(), 0);
+    assert!(m.variable_length());
+    assert!(!market("testdata/AAPL-1.txt").variable_length());
+    assert!(m.fitness(&m.generate_genotype(false).genotype) != Fitness::Invalid);
+}
+
+fn exits() -> Options {
+    Options {
+        exits: true,
+        ..Default::default()
+    }
+}
+
+// The days each sell is made on
+fn sells(m: &Market, g: &[u8]) -> Vec<(usize, f64)> {
+    m.ledger(g).unwrap()[0]
+        .ledger
+        .iter()
+        .filter(|t| !t.buy)
+        .map(|t| (t.day, t.price))
+        .collect()
+}
+
+#[test]
+fn stops_parse() {
+    assert_eq!(
+        Stops::parse(b"l050t100p250h005").unwrap(),
+        Stops {
+            loss: 0.05,
+            trailing: 0.1,
+            profit: 0.25,
+            hold: 5,
+        }
+    );
+    assert_eq!(Stops::parse(b"l000t000p000h000").unwrap(), Stops::default());
+}
+
+// Ensure genotypes decode to strategies that read as words
+#[test]
+fn strategy_decode() {
+    let s = Strategy::decode(b"e010|m002|s101").unwrap();
+
+    assert_eq!(
+        s.entry.indicators,
+        [
+            Indicator::Average(Average::Exponential(10)),
+            Indicator::Average(Average::Maximum(2)),
+            Indicator::Average(Average::Simple(101)),
+        ]
+    );
+    assert_eq!((s.exit, s.stops, s.direction), (None, None, None));
+    assert_eq!(
+        s.to_string(),
+        "buy when price > 10-day EMA OR price > 2-day max OR price > 101-day SMA, otherwise sell"
+    );
+
+    let s = Strategy::decode(b"r714&b120|o710/c026|v720|n005/l050t000p250h005/s").unwrap();
+
+    assert_eq!(s.direction, Some(Direction::Short));
+    assert_eq!(
+        s.to_string(),
+        "buy when 14-day RSI > 70 AND price > 20-day mean -2 standard deviations AND \
+         10-day change > 2%, sell when 26-day MACD > signal OR volume > 1.4× its 20-day average \
+         OR price > 5-day min, with a 5% stop-loss, a 25% take-profit, holding at least 5 days \
+         before selling, trading short"
+    );
+}
+
+// Ensure strategies encode back to the genotypes they were decoded from, and
+// values no gene can hold are reported
+#[test]
+fn strategy_encode() {
+    let m = market_with(
+        "testdata/csv/one-month-linear.csv",
+        Options {
+            exits: true,
+            ..short()
+        },
+    );
+
+    for _ in 0..200 {
+        let g = m.generate_genotype(false).genotype;
+        assert_eq!(m.strategy(&g).unwrap().encode().unwrap(), g);
+    }
+
+    let mut s = Strategy::decode(b"s020&r714|v715").unwrap();
+    assert_eq!(s.encode().unwrap(), b"s020&r714|v715");
+
+    s.entry.indicators[1] = Indicator::Rsi {
+        days: 14,
+        level: 75.0,
+    };
+    assert_eq!(
+        s.encode().unwrap_err(),
+        "Can't write 14-day RSI > 75 as a gene: the level must be a multiple of 10 up to 90"
+    );
+
+    s.entry.indicators[1] = Indicator::Average(Average::Simple(1000));
+    assert_eq!(
+        s.encode().unwrap_err(),
+        "Can't write price > 1000-day SMA as a gene: it can have at most 999 days"
+    );
+
+    s.entry.indicators[1] = Indicator::Macd(26);
+    s.stops = Some(Stops {
+        loss: 0.0505,
+        ..Default::default()
+    });
+    assert_eq!(s.encode().unwrap_err(), "Exit genes and stops must be given together");
+
+    s.exit = Some(s.entry);
+    assert_eq!(
+        s.encode().unwrap_err(),
+        "Can't write a 5.1% stop-loss as a stop: it must be a multiple of 0.1% up to 99.9%"
+    );
+}
+
+// Ensure invalid genotypes say where they go wrong, and genotypes that don't
+// match the market's options are invalid instead of panicking
+#[test]
+fn strategy_invalid() {
+    let error = |g: &[u8]| Strategy::decode(g).unwrap_err();
+
+    assert_eq!(
+        error(b"s020&r714"),
+        "genes s020&r714 at byte 0 are 9 bytes, expected 14 such as s020&r714|v715"
+    );
+    assert_eq!(
+        error(b"s020&r714+v715"),
+        "expected & or | at byte 9, found +"
+    );
+    assert_eq!(
+        error(b"s020&r714|v715/e010|e0x0|b120/l050t100p250h005"),
+        "gene e0x0 needs three digits after its letter at byte 20"
+    );
+    assert_eq!(
+        error(b"s020&r714|v715/e010|e010|b120/l050t100p250"),
+        "stops l050t100p250 are 12 bytes, expected 16 such as l050t100p250h005 at byte 30"
+    );
+    assert_eq!(
+        error(b"s020&r714|v715/lb"),
+        "direction lb at byte 15 should be a single l, s or b"
+    );
+
+    let m = market("testdata/AAPL-1.txt");
+    assert!(m.strategy(b"s020&r714|v715").is_ok());
+    assert_eq!(
+        m.strategy(b"s020&r714|v715/b").unwrap_err(),
+        "a direction is expected only with --short"
+    );
+    assert_eq!(m.fitness(b"s020&r714|v715/b"), Fitness::Invalid);
+    assert_eq!(m.fitness(b"s020&r714|x715"), Fitness::Invalid);
+    assert!(m.returns(b"s020&r714|x715").is_err());
+    assert_eq!(
+        m.format(&genotype("s020&r714|v715/b")),
+        "s020&r714|v715/b is invalid: a direction is expected only with --short"
+    );
+
+    let m = market_with("testdata/AAPL-1.txt", exits());
+    assert_eq!(
+        m.strategy(b"s020&r714|v715").unwrap_err(),
+        "exit genes and stops are expected with --exits"
+    );
+}
+
+// Ensure stop-losses, trailing stops and take-profits sell from the entry price
+// and the highest price since. s001 buys whenever the price rises and m999 never
+// sells, so only the stops do.
+#[test]
+fn market_stops() {
+    let m = market_from(vec![10.0, 11.0, 12.0, 10.5, 10.0, 13.0], exits());
+    assert_eq!(sells(&m, b"s001|s001|s001/m999|m999|m999/l000t000p000h000"), [(5, 13.0)]);
+    assert_eq!(
+        sells(&m, b"s001|s001|s001/m999|m999|m999/l050t000p000h000"),
+        [(4, 10.0)]
+    );
+
+    let m = market_from(vec![10.0, 11.0, 14.0, 13.0, 12.5, 15.0], exits());
+    assert_eq!(
+        sells(&m, b"s001|s001|s001/m999|m999|m999/l000t100p000h000"),
+        [(4, 12.5)]
+    );
+
+    // the stop sells even though the entry genes would buy
+    let m = market_from(vec![10.0, 11.0, 12.2, 13.0], exits());
+    assert_eq!(
+        sells(&m, b"s001|s001|s001/m999|m999|m999/l000t000p100h000"),
+        [(2, 12.2)]
+    );
+}
+
+// Ensure exit genes sell instead of the entry genes not holding, once the
+// position has been held long enough. b001 always holds.
+#[test]
+fn market_exits() {
+    let m = market_from(vec![10.0, 11.0, 10.5, 10.2, 10.1, 10.0], exits());
+    assert_eq!(
+        sells(&m, b"s001|s001|s001/b001|b001|b001/l000t000p000h000"),
+        [(2, 10.5)]
+    );
+    assert_eq!(
+        sells(&m, b"s001|s001|s001/b001|b001|b001/l000t000p000h002"),
+        [(3, 10.2)]
+    );
+
+    // without exit genes the strategy holds until the last day
+    assert_eq!(sells(&m, b"s001|s001|s001/m999|m999|m999/l000t000p000h000"), [(5, 10.0)]);
+
+    // the genes alone sell as soon as they stop holding
+    let m = market_from(vec![10.0, 11.0, 10.5, 10.2, 10.1, 10.0], Options::default());
+    assert_eq!(sells(&m, b"s001|s001|s001"), [(2, 10.5)]);
+}
+
+// Ensure genotypes with exits keep their layout when generated and mutated
+#[test]
+fn market_generate_exits() {
+    let m = market_with("testdata/tests/one-year-linear.txt", exits());
+    assert_eq!(m.len(), 46);
+
+    for _ in 0..100 {
+        let mut g = m.generate_genotype(false);
+        m.mutate(1.0, true, &mut g);
+
+        assert_eq!(g.len(), 46);
+        assert_eq!((g.genotype[14], g.genotype[29]), (b'/', b'/'));
+        assert_eq!(
+            [g.genotype[30], g.genotype[34], g.genotype[38], g.genotype[42]],
+            *b"ltph"
+        );
+        assert!(matches!(g.fitness, Fitness::Valid(_)));
+    }
+}
+
+fn history(ticker: &str, dates: &[&str], close: Vec<f64>) -> History {
+    History {
+        ticker: ticker.to_string(),
+        times: dates.iter().map(|d| time(d)).collect(),
+        close,
+        ..Default::default()
+    }
+}
+
+fn portfolio(histories: Vec<History>, options: Options) -> Market {
+    Market {
+        histories,
+        options: Options {
+            portfolio: true,
+            ..options
+        },
+        ..Market::new(Vec::new()).unwrap()
+    }
+}
+
+// Ensure one account trades every stock on the days they share, and each stock
+// is credited with the profit made on it. B only starts trading on the second day.
+#[test]
+fn market_portfolio() {
+    let histories = vec![
+        history("A", &["2022-01-03", "2022-01-04", "2022-01-05"], vec![10.0, 10.0, 12.0]),
+        history("B", &["2022-01-04", "2022-01-05"], vec![20.0, 25.0]),
+    ];
+
+    let m = portfolio(histories.clone(), Options::default());
+    let (dates, bars) = m.calendar();
+    assert_eq!(
+        dates,
+        [Some(time("2022-01-03")), Some(time("2022-01-04")), Some(time("2022-01-05"))]
+    );
+    assert_eq!(bars, [vec![(0, 0)], vec![(0, 1), (1, 0)], vec![(0, 2), (1, 1)]]);
+
+    // half of the account each: 1000 shares of A at 10, then 500 of B at 20
+    let held = m.buy_and_hold();
+    assert_eq!(held.iter().map(|s| s.profit).collect::<Vec<_>>(), [2000.0, 2500.0]);
+    assert_eq!(held[0].equity, [0.0, 0.0, 2000.0]);
+    assert!(m
+        .format(&genotype("s000|s000|s000"))
+        .contains("vs $4500.00 (A $2000.00, B $2500.00) buying and holding"));
+
+    let m = portfolio(
+        histories,
+        Options {
+            sizing: Sizing::Fraction,
+            fraction: 0.25,
+            ..Default::default()
+        },
+    );
+    let held = m.buy_and_hold();
+    assert_eq!(held.iter().map(|s| s.profit).collect::<Vec<_>>(), [1000.0, 1250.0]);
+}
+
+// Ensure --fraction can only size positions that buy something and spend at most
+// the whole account
+#[test]
+fn options_fraction_range() {
+    let fraction = |f: &str| {
+        Args::try_parse_from(["genetic", "--sizing", "fraction", &format!("--fraction={f}")])
+            .map(|args| args.stocks.fraction)
+            .map_err(|e| e.to_string())
+    };
+
+    assert_eq!(fraction("1"), Ok(1.0));
+    assert_eq!(fraction("0.25"), Ok(0.25));
+
+    for f in ["0", "-0.5", "1.5"] {
+        assert!(fraction(f).unwrap_err().contains("must be more than 0 and at most 1"));
+    }
+
+    assert!(fraction("half").is_err());
+}
+
+// Ensure less volatile stocks get more of the portfolio
+#[test]
+fn market_portfolio_volatility() {
+    let alternating = |jump: f64| (0..30).map(|d| 100.0 + jump * (d % 2) as f64).collect();
+    let (calm, volatile) = (
+        history("A", &[], alternating(1.0)),
+        history("B", &[], alternating(4.0)),
+    );
+    assert_eq!(calm.volatility(19), None);
+
+    let (a, b) = (calm.volatility(21).unwrap(), volatile.volatility(21).unwrap());
+    assert!(a < b);
+
+    let m = portfolio(
+        vec![calm, volatile],
+        Options {
+            sizing: Sizing::Volatility,
+            ..Default::default()
+        },
+    );
+
+    // s001 first buys on day 1, before there's enough history, so equally
+    let ledger = m.ledger(b"s001|s001|s001").unwrap();
+    assert_eq!((ledger[0].ledger[0].day, ledger[0].ledger[0].shares), (1, 99.0));
+    assert_eq!((ledger[1].ledger[0].day, ledger[1].ledger[0].shares), (1, 96.0));
+
+    // and then in proportion to the inverse volatility, of a portfolio that's
+    // only cash after selling on day 20
+    let weights = [(1.0 / a) / (1.0 / a + 1.0 / b), (1.0 / b) / (1.0 / a + 1.0 / b)];
+    let value = 20000.0 + ledger.iter().map(|s| s.equity[20]).sum::<f64>();
+
+    for ((simulation, weight), price) in ledger.iter().zip(weights).zip([101.0, 104.0]) {
+        let trade = simulation.ledger.iter().find(|t| t.buy && t.day == 21).unwrap();
+        assert_eq!(trade.shares, (value * weight / price).floor());
+    }
+}
+
+// Ensure the profits credited to each stock add up to the account's, and the
+// objective is measured on the account
+#[test]
+fn market_portfolio_attribution() {
+    let m = Market::with_options(
+        vec!["testdata/AAPL-1.txt".to_string(), "testdata/DDS-1.txt".to_string()],
+        Options {
+            portfolio: true,
+            exits: true,
+            commission: 5.0,
+            ..Default::default()
+        },
+    );
+    let g = b"e010|m002|s101/b120|b120|b120/l050t000p250h003";
+
+    assert_close(m.fitness(g).unwrap(), m.returns(g).unwrap().iter().sum());
+    assert_ne!(
+        m.returns(g).unwrap(),
+        market("testdata/AAPL-1.txt").returns(b"e010|m002|s101").unwrap()
+    );
+
+    let sharpe = Market {
+        options: Options {
+            objective: Objective::Sharpe,
+            ..m.options.clone()
+        },
+        ..m
+    };
+    let stocks = Objective::Sharpe.score(&sharpe.simulate(g).unwrap());
+    assert_ne!(sharpe.fitness(g).unwrap(), stocks);
+}
+
+fn short() -> Options {
+    Options {
+        short: true,
+        ..Default::default()
+    }
+}
+
+// Ensure the direction gene trades long, short or both ways. s001 holds on the
+// days one-month-sinusoidal.txt rises, and it falls from day 10 to 20.
+#[test]
+fn market_short() {
+    let m = market_with("testdata/tests/one-month-sinusoidal.txt", short());
+    assert_eq!(m.len(), 16);
+
+    let long = m.returns(b"s001|s001|s001/l").unwrap()[0];
+    let short = m.returns(b"s001|s001|s001/s").unwrap()[0];
+    let both = m.returns(b"s001|s001|s001/b").unwrap()[0];
+    assert!(long > 0.0);
+    assert!(short > 0.0);
+    assert!(both > long && both > short);
+
+    // short sales are sells that open positions and covers are buys
+    let trades = &m.ledger(b"s001|s001|s001/s").unwrap()[0].ledger;
+    assert_eq!((trades[0].day, trades[0].buy, trades[0].price), (11, false, 1.09));
+    assert_eq!((trades[1].day, trades[1].buy, trades[1].price), (21, true, 1.01));
+
+    // without the gene only long positions are taken
+    let m = market("testdata/tests/one-month-sinusoidal.txt");
+    assert_close(m.returns(b"s001|s001|s001").unwrap()[0], long);
+}
+
+// Ensure the stops of short positions are mirrored. The entry genes never hold
+// and b001 always does, so the exit genes sell short on the first day they can.
+#[test]
+fn market_short_stops() {
+    let options = Options {
+        exits: true,
+        ..short()
+    };
+    let m = market_from(vec![10.0, 10.0, 9.0, 10.4, 10.6, 8.0], options.clone());
+    let g = b"s001&m999&m999/b001|b001|b001/l050t000p000h000/s";
+    let covers = |m: &Market, g: &[u8]| -> Vec<(usize, f64)> {
+        m.ledger(g).unwrap()[0].ledger.iter().filter(|t| t.buy).map(|t| (t.day, t.price)).collect()
+    };
+
+    // shorted at 10 and covered when the price rises 5% above it
+    assert_eq!(covers(&m, g), [(4, 10.6)]);
+    // or falls 10% below it, and then shorted again
+    assert_eq!(
+        covers(&m, b"s001&m999&m999/b001|b001|b001/l000t000p100h000/s"),
+        [(2, 9.0), (5, 8.0)]
+    );
+    // or rises 15% above its lowest of 9
+    assert_eq!(
+        covers(&m, b"s001&m999&m999/b001|b001|b001/l000t150p000h000/s"),
+        [(3, 10.4), (5, 8.0)]
+    );
+    assert_eq!(covers(&m, b"s001&m999&m999/b001|b001|b001/l000t000p000h000/s"), [(5, 8.0)]);
+
+    let m = market_with("testdata/tests/one-year-linear.txt", options);
+    assert_eq!(m.len(), 48);
+
+    for _ in 0..100 {
+        let mut g = m.generate_genotype(false);
+        m.mutate(1.0, true, &mut g);
+
+        assert_eq!(g.len(), 48);
+        assert_eq!(g.genotype[46], b'/');
+        assert!(b"lsb".contains(&g.genotype[47]));
+        assert!(matches!(g.fitness, Fitness::Valid(_)));
+    }
+}
+
+// Ensure leverage borrows to buy more, with interest, until a margin call
+#[test]
+fn market_leverage() {
+    let leverage = |borrow_rate: f64, margin: f64| Options {
+        leverage: Some(2.0),
+        borrow_rate,
+        margin,
+        ..Default::default()
+    };
+
+    // 4000 shares at 10 with $20000 borrowed
+    let m = market_from(vec![10.0, 11.0, 12.0], leverage(0.0, 0.0));
+    assert_eq!(m.buy_and_hold()[0].profit, 8000.0);
+
+    // paying 0.1% on what's borrowed at the end of the first two days
+    let m = market_from(vec![10.0, 11.0, 12.0], leverage(0.252, 0.0));
+    assert_close(m.buy_and_hold()[0].profit, 8000.0 - 20.0 - 20.02);
+
+    // $4000 of equity is less than a quarter of the $24000 of shares at 6
+    let m = market_from(vec![10.0, 6.0, 5.0, 12.0], leverage(0.0, 0.25));
+    assert_eq!(m.buy_and_hold()[0].profit, -16000.0);
+
+    let m = market_from(vec![10.0, 6.0, 5.0, 12.0], leverage(0.0, 0.0));
+    assert_eq!(m.buy_and_hold()[0].profit, 8000.0);
+}
+
+fn synthetic(process: Process, seed: u64) -> synthetic::Options {
+    synthetic::Options {
+        process,
+        seed,
+        length: 1000,
+        ..Default::default()
+    }
+}
+
+// Ensure the same seed always generates the same prices, from the first price
+#[test]
+fn synthetic_seeded() {
+    for process in Process::value_variants() {
+        let prices = synthetic(*process, 7).prices();
+
+        assert_eq!(prices.len(), 1000);
+        assert_eq!(prices[0], 100.0);
+        assert!(prices.iter().all(|p| *p >= 0.01), "{process}");
+        assert_eq!(prices, synthetic(*process, 7).prices());
+        assert_ne!(prices, synthetic(*process, 8).prices());
+    }
+}
+
+// Ensure geometric Brownian motion has the drift and volatility it's given,
+// and a mean-reverting price stays closer to its mean
+#[test]
+fn synthetic_gbm() {
+    let options = synthetic::Options {
+        length: 100_000,
+        ..synthetic(Process::Gbm, 1)
+    };
+    let returns: Vec<f64> = options.prices().windows(2).map(|p| (p[1] / p[0]).ln()).collect();
+    let mean = returns.iter().sum::<f64>() / returns.len() as f64;
+    let deviation = (returns.iter().map(|r| (r - mean).powi(2)).sum::<f64>()
+        / returns.len() as f64)
+        .sqrt();
+
+    assert!((mean * 252.0 - (0.05 - 0.02)).abs() < 0.02, "{mean}");
+    assert!((deviation * 252f64.sqrt() - 0.2).abs() < 0.002, "{deviation}");
+
+    // the mean grows at the drift from the first price
+    let distance = |process: Process| {
+        let prices = synthetic(process, 1).prices();
+
+        prices
+            .iter()
+            .enumerate()
+            .map(|(day, p)| (p.ln() - (100f64.ln() + 0.05 * day as f64 / 252.0)).abs())
+            .sum::<f64>()
+            / prices.len() as f64
+    };
+    assert!(distance(Process::MeanReverting) < distance(Process::Gbm) / 2.0);
+}
+
+// Ensure a trend without noise is a straight line, so strategies can be tested
+// on it deterministically
+#[test]
+fn synthetic_trend() {
+    let options = synthetic::Options {
+        drift: 0.252,
+        volatility: 0.0,
+        price: 10.0,
+        length: 11,
+        ..synthetic(Process::TrendNoise, 0)
+    };
+    let prices = options.prices();
+
+    for (day, price) in prices.iter().enumerate() {
+        assert_close(*price, 10.0 * (1.0 + day as f64 / 1000.0));
+    }
+
+    let m = market_from(prices, Options::default());
+    assert_close(m.buy_and_hold()[0].profit, 2000.0 * 0.1);
+}
+
+// Ensure generated histories are read back as they were written
+#[test]
+fn synthetic_file() {
+    let file = std::env::temp_dir().join(format!("synthetic-{}.txt", std::process::id()));
+    let options = synthetic::Options {
+        file: Some(file.to_string_lossy().into_owned()),
+        ticker: "JUMP".to_string(),
+        from: date("2021-12-31"),
+        length: 300,
+        ..synthetic(Process::Jump, 3)
+    };
+    options.write();
+
+    let m = market_with(&file.to_string_lossy(), Options::default());
+    std::fs::remove_file(&file).unwrap();
+
+    let history = &m.histories[0];
+    assert_eq!(history.ticker, "JUMP");
+    assert_eq!(history.name, "Jump diffusion (seed 3)");
+    assert_eq!(history.range, Some((date("2021-12-01"), date("2023-02-28"))));
+    assert_eq!(history.order, Order::OldestFirst);
+    assert_eq!(history.len(), 300);
+
+    for (read, written) in history.close.iter().zip(options.prices()) {
+        assert!((read - written).abs() <= 0.005);
+    }
+}
+
+// Ensure chances and rates that can't be drawn are rejected when they're given
+#[test]
+fn synthetic_options_range() {
+    let generate = |args: &[&str]| {
+        Args::try_parse_from(["genetic", "generate"].iter().chain(args)).map_err(|e| e.to_string())
+    };
+
+    assert!(generate(&["--switch-chance", "1", "--jumps", "0"]).is_ok());
+    assert!(generate(&["--switch-chance", "2"]).unwrap_err().contains("must be from 0 to 1"));
+    assert!(generate(&["--switch-chance=-0.1"]).unwrap_err().contains("must be from 0 to 1"));
+    assert!(generate(&["--jumps=-1"]).unwrap_err().contains("can't be negative"));
+}
+
+// Every stock history in testdata
+fn test_files() -> Vec<String> {
+    let mut files = Vec::new();
+    let mut dirs = vec![std::path::PathBuf::from("testdata")];
+
+    while let Some(dir) = dirs.pop() {
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+
+            if path.is_dir() {
+                dirs.push(path);
+            } else if matches!(path.extension().and_then(|e| e.to_str()), Some("txt" | "csv")) {
+                files.push(path.to_string_lossy().into_owned());
+            }
+        }
+    }
+
+    files.sort();
+    files
+}
+
+// Ensure every streaming average matches computing it from scratch on every
+// day of every test file, including from genes that are read from any day
+#[test]
+fn indicators_match_recomputation() {
+    let files = test_files();
+    assert!(files.len() > 40);
+
+    for file in files {
+        // one export has its own column names, and two are deliberately unreadable
+        let columns = if file.ends_with("mapped.csv") {
+            vec!["date=timestamp".into(), "close=close_price".into()]
+        } else if file.ends_with("unordered.csv") || file.ends_with("empty.csv") {
+            continue;
+        } else {
+            Vec::new()
+        };
+        let m = market_with(
+            &file,
+            Options {
+                columns,
+                ..Default::default()
+            },
+        );
+        let history = &m.histories[0];
+        let stock = &history.close;
+
+        for days in [0, 1, 2, 7, 30, 200] {
+            for average in [
+                Average::Simple(days),
+                Average::Exponential(days),
+                Average::Maximum(days),
+                Average::Minimum(days),
+                Average::Weighted(days),
+            ] {
+                let mut moving = average.moving();
+                let mut reader = Reader::new(Indicator::Average(average));
+
+                for (day, price) in stock.iter().enumerate() {
+                    let expected = Market::get_average(stock, day, &average);
+                    let value = moving.value();
+
+                    if days == 0 || day < days {
+                        assert_eq!(value, None, "{file} {average:?} on day {day}");
+                    } else {
+                        let error = (value.unwrap() - expected).abs() / expected.abs().max(1.0);
+                        assert!(error < 1e-9, "{file} {average:?} on day {day}");
+                    }
+
+                    assert_eq!(reader.read(history, day).value, value);
+                    moving.push(*price);
+                }
+
+                // readers start again when they skip days
+                let mut reader = Reader::new(Indicator::Average(average));
+
+                for day in (0..stock.len()).step_by(days + 3) {
+                    let value = reader.read(history, day).value;
+                    let expected = Market::get_average(stock, day, &average);
+
+                    assert_eq!(value.is_some(), days > 0 && day >= days);
+                    assert!(value.is_none_or(|v| (v - expected).abs() < 1e-9 * v.abs().max(1.0)));
+                }
+            }
+        }
+    }
+}
+
+// Ensure the streaming EMA matches recomputing it over the same window on every
+// day of a series longer than any test file, so rounding errors don't build up
+#[test]
+fn indicators_ema_long_series() {
+    let prices: Vec<f64> = (0..10_000)
+        .map(|i| 100.0 + (i as f64 * 0.37).sin() * 20.0 + (i % 7) as f64)
+        .collect();
+
+    for days in [1, 2, 3, 10, 50] {
+        let mut ema = Ema::new(days);
+
+        for (day, price) in prices.iter().enumerate() {
+            ema.push(*price);
+
+            match ema.value() {
+                Some(value) => {
+                    let expected = exponential(|d| prices[d], day + 1, days);
+                    assert!((value - expected).abs() < 1e-9, "{days} days on day {day}");
+                }
+                None => assert!(day + 1 < days),
+            }
+        }
+    }
+
+    // with three days every price weighs half the next: (4 + 2 / 2 + 1 / 4) / 1.75
+    let mut ema = Ema::new(3);
+
+    for price in [1.0, 2.0, 4.0] {
+        ema.push(price);
+    }
+
+    assert_eq!(ema.value(), Some(3.0));
+    ema.push(8.0);
+    assert_eq!(ema.value(), Some(6.0));
+}
+
+// Ensure indicator series are computed once per history and shared by genes
+// that only differ in their thresholds, without changing fitness
+#[test]
+fn indicator_cache() {
+    let files = vec!["testdata/AAPL-1.txt".to_string(), "testdata/DDS-1.txt".to_string()];
+    let m = Market::new(files.clone()).unwrap();
+    assert!(m.cache.is_empty());
+
+    let fitness = m.fitness(b"s020&r714|r314");
+    assert_eq!(m.cache.len(), 4);
+    assert_eq!(m.fitness(b"s020&r714|r314"), fitness);
+    assert_eq!(m.fitness(b"r514|s020&r914"), m.fitness(b"r514|s020&r914"));
+    assert_eq!(m.cache.len(), 4);
+
+    let rsi = |level| Indicator::Rsi { days: 14, level };
+    assert!(Arc::ptr_eq(&m.series(1, rsi(70.0)), &m.series(1, rsi(30.0))));
+
+    // each history has its own
+    assert!(!Arc::ptr_eq(&m.series(0, rsi(70.0)), &m.series(1, rsi(70.0))));
+    let fresh = Market::new(files).unwrap();
+    assert_eq!(m.fitness(b"s021&r714|r314"), fresh.fitness(b"s021&r714|r314"));
+    assert_eq!(m.cache.len(), 6);
+
+    // the series are what readers read each day
+    let history = &m.histories[0];
+    let mut reader = Reader::new(rsi(70.0));
+    for (day, value) in m.series(0, rsi(70.0)).iter().enumerate() {
+        assert_eq!(*value, reader.read(history, day).value);
+    }
+}
+
+// Paper trade every bar of a market's only history
+fn paper(m: &Market, options: Options, genotype: &[u8]) -> (Paper, Vec<Tick>) {
+    let history = &m.histories[0];
+    let mut paper = Paper::new(options, &history.ticker, genotype, history.year()).unwrap();
+    let ticks = (0..history.len())
+        .map(|day| paper.push(history.bar(day), day + 1 == history.len()).unwrap())
+        .collect();
+
+    (paper, ticks)
+}
+
+// Ensure paper trading a history bar by bar makes exactly the backtest's trades,
+// with stops, short positions, borrowing and trading at the open
+#[test]
+fn paper_matches_backtest() {
+    let leveraged = Options {
+        exits: true,
+        leverage: Some(2.0),
+        borrow_rate: 0.05,
+        margin: 0.25,
+        commission: 5.0,
+        ..short()
+    };
+    let at_open = Options {
+        trade_at_open: true,
+        ..exits()
+    };
+    let intraday = "testdata/csv/intraday.csv";
+    let mut cases = vec![
+        ("testdata/AAPL-1.txt", Options::default(), "s002|e003|m004"),
+        ("testdata/AAPL-1.txt", Options::default(), "c026&r714|o710"),
+        ("testdata/DDS-1.txt", exits(), "r714&b120|o710/c026|e010|n005/l050t100p250h005"),
+        (intraday, at_open.clone(), "s003|e005|v705/m004&n003&s001/l050t000p100h002"),
+    ]
+    .into_iter()
+    .map(|(file, options, genotype)| (file, options, genotype.as_bytes().to_vec()))
+    .collect::<Vec<_>>();
+
+    for (file, options) in [("testdata/F-1.txt", leveraged), (intraday, at_open)] {
+        let m = market_with(file, options.clone());
+
+        for _ in 0..20 {
+            cases.push((file, options.clone(), m.generate_genotype(false).genotype));
+        }
+    }
+
+    for (file, options, genotype) in cases {
+        let m = market_with(file, options.clone());
+        let (paper, ticks) = paper(&m, options, &genotype);
+        let backtest = m.ledger(&genotype).unwrap().remove(0);
+
+        assert_eq!(paper.trades(), backtest.ledger, "{}", String::from_utf8_lossy(&genotype));
+        assert_close(paper.profit(), backtest.profit);
+        assert_close(ticks.last().unwrap().equity, m.funds + backtest.profit);
+        assert_eq!(ticks.iter().map(|t| t.trades.len()).sum::<usize>(), backtest.trades);
+    }
+}
+
+// Ensure each bar's signal comes with the genes' values, and bars read from
+// standard input take a price, a date and a volume
+#[test]
+fn paper_ticks() {
+    let options = Options::default();
+    let mut paper = Paper::new(options, "stdin", b"s002&s002|s002", TRADING_DAYS).unwrap();
+    let bars: Vec<Bar> = ["10", "11", "12", "2022-01-05, 9, 500"]
+        .iter()
+        .map(|s| s.parse().unwrap())
+        .collect();
+
+    assert_eq!(bars[3].time, Some(time("2022-01-05")));
+    assert_eq!(bars[3].volume, Some(500.0));
+
+    let ticks: Vec<Tick> = bars.into_iter().map(|bar| paper.push(bar, false).unwrap()).collect();
+    let signals: Vec<Signal> = ticks.iter().map(|t| t.signal).collect();
+
+    assert_eq!(signals, [Signal::Hold, Signal::Hold, Signal::Buy, Signal::Sell]);
+    assert_eq!(ticks[2].genes[0], ("s002".to_string(), Some(10.5), true));
+    assert_eq!(
+        ticks[2].to_string(),
+        "Bar 2 at 12.00: BUY (s002 10.50 bullish, s002 10.50 bullish, s002 10.50 bullish), \
+         bought 1666 shares at $12.00, equity $20000.00"
+    );
+    assert_eq!(
+        ticks[3].to_string(),
+        "2022-01-05 at 9.00: SELL (s002 11.50 bearish, s002 11.50 bearish, s002 11.50 bearish), \
+         sold 1666 shares at $9.00, equity $15002.00"
+    );
+    assert_eq!(
+        ticks[0].to_string(),
+        "Bar 0 at 10.00: HOLD (s002 none, s002 none, s002 none), equity $20000.00"
+    );
+    assert_close(paper.profit(), -4998.0);
+
+    assert!("abc".parse::<Bar>().is_err());
+    assert!("2022-13-01,10".parse::<Bar>().is_err());
+    assert!(Paper::new(short(), "stdin", b"s002&s002|s002", TRADING_DAYS).is_err());
+}
+
+// Ensure bars without an open are refused when trading at the open, instead of
+// panicking, and leave the paper trade as it was
+#[test]
+fn paper_trade_at_open() {
+    let options = Options {
+        trade_at_open: true,
+        ..Default::default()
+    };
+    let mut paper = Paper::new(options, "stdin", b"s002&s002|s002", TRADING_DAYS).unwrap();
+    let bar: Bar = "10".parse().unwrap();
+
+    assert_eq!(
+        paper.push(bar, false).unwrap_err(),
+        "Trading at the open needs the open of every bar"
+    );
+    assert_eq!(paper.push(Bar { open: Some(9.5), ..bar }, true).unwrap().day, 0);
+    assert!(paper.push(Bar { open: Some(9.5), ..bar }, false).is_err());
+}
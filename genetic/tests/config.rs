@@ -0,0 +1,137 @@
+use genetic::{config, genetic::*};
+
+fn load(args: &[&str]) -> (Args, String) {
+    config::load(std::iter::once("genetic").chain(args.iter().copied()))
+}
+
+#[test]
+fn config_toml() {
+    let (args, _) = load(&["--config", "testdata/config/stocks.toml"]);
+
+    assert!(matches!(
+        args.problem,
+        genetic::problems::ProblemType::Stocks
+    ));
+    assert_eq!(args.file, ["testdata/AAPL-1.txt", "testdata/DDS-1.txt"]);
+    assert_eq!(args.best, 3);
+    assert_eq!(args.max_generations, 5);
+    assert_eq!(args.population, 20);
+    assert!(matches!(args.selection_method, SelectionMethod::Remainder));
+    assert!(args.force_mutation);
+    assert_eq!(args.mutation_rate, 0.05);
+
+    // untouched parameters keep their defaults
+    assert_eq!(args.skip, 0.1);
+    assert!(!args.force_create);
+}
+
+#[test]
+fn config_yaml() {
+    let (args, _) = load(&["--config", "testdata/config/knapsack.yaml"]);
+
+    assert!(matches!(
+        args.problem,
+        genetic::problems::ProblemType::Knapsack
+    ));
+    assert_eq!(args.file, ["testdata/knapsack/test1.kp"]);
+    assert!(args.force_create);
+    assert_eq!(args.max_generations, 10);
+    assert!(matches!(args.sex_method, SexMethod::Two));
+}
+
+// Ensure flags given on the command line override the file
+#[test]
+fn config_cli_overrides() {
+    let (args, _) = load(&[
+        "--best",
+        "7",
+        "--config",
+        "testdata/config/stocks.toml",
+        "--file",
+        "testdata/GE-1.txt",
+        "-m",
+        "0.2",
+    ]);
+
+    assert_eq!(args.best, 7);
+    assert_eq!(args.file, ["testdata/GE-1.txt"]);
+    assert_eq!(args.mutation_rate, 0.2);
+    assert_eq!(args.population, 20);
+}
+
+// Ensure the effective configuration reproduces the same run
+#[test]
+fn config_round_trip() {
+    let (mut args, settings) = load(&[
+        "--config",
+        "testdata/config/stocks.toml",
+        "--genitors",
+        "e010|m002|s101",
+        "--skip",
+        "0.25",
+    ]);
+
+    let file = std::env::temp_dir().join(format!("genetic-{}.toml", std::process::id()));
+    std::fs::write(&file, &settings).unwrap();
+
+    let (mut reloaded, again) = load(&["--config", file.to_str().unwrap()]);
+    std::fs::remove_file(&file).unwrap();
+
+    args.config = None;
+    reloaded.config = None;
+
+    assert_eq!(format!("{args:?}"), format!("{reloaded:?}"));
+    assert_eq!(settings, again);
+}
+
+#[test]
+#[should_panic(expected = "Unknown option")]
+fn config_unknown_option() {
+    let file = std::env::temp_dir().join(format!("genetic-unknown-{}.toml", std::process::id()));
+    std::fs::write(&file, "colour = \"blue\"\n").unwrap();
+
+    let result = std::panic::catch_unwind(|| load(&["--config", file.to_str().unwrap()]));
+    std::fs::remove_file(&file).unwrap();
+
+    if let Err(e) = result {
+        std::panic::resume_unwind(e);
+    }
+}
+
+// Ensure --output saves the configuration with the results, and reproduces them
+// when passed back to --config
+#[test]
+fn config_output() {
+    let file = std::env::temp_dir().join(format!("genetic-output-{}.toml", std::process::id()));
+    let run = |args: &[&str]| {
+        let output = std::process::Command::new(env!("CARGO_BIN_EXE_genetic"))
+            .args(args)
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        String::from_utf8(output.stdout).unwrap()
+    };
+
+    let printed = run(&[
+        "--problem",
+        "stocks",
+        "--file",
+        "testdata/AAPL-1.txt",
+        "--evaluate",
+        "--genitors",
+        "e010|m002|s101",
+        "--output",
+        file.to_str().unwrap(),
+    ]);
+    let saved = std::fs::read_to_string(&file).unwrap();
+    let result = printed.lines().last().unwrap();
+
+    assert!(saved.contains("genitors = [\"e010|m002|s101\"]"));
+    assert!(!saved.contains("output"));
+    assert!(saved.ends_with(&format!("# {result}\n")));
+
+    let again = run(&["--config", file.to_str().unwrap()]);
+    std::fs::remove_file(&file).unwrap();
+
+    assert_eq!(again.lines().last().unwrap(), result);
+}
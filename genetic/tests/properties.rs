@@ -0,0 +1,161 @@
+use genetic::genetic::*;
+use genetic::problems::{knapsack::Knapsack, Problem};
+use indicatif::ProgressBar;
+use proptest::prelude::*;
+
+// A knapsack that can hold every item, over an arbitrary alphabet
+fn knapsack(length: usize, alphabet: Vec<u8>) -> Knapsack {
+    Knapsack {
+        alphabet,
+        items: vec![(1, 1); length],
+        max_weight: length,
+    }
+}
+
+fn generation(problem: Knapsack, sex_method: SexMethod, mutation_rate: f64) -> Generation {
+    Generation {
+        best: 1,
+        force_create: false,
+        detect_crowding: 0.0,
+        max_generations: 1,
+        force_mutation: false,
+        population: Vec::new(),
+        intermediate: Vec::new(),
+        skip: 0.0,
+        mutation_rate,
+        problem: Box::new(problem),
+        selection_method: SelectionMethod::Equal,
+        sex_method,
+        progress: ProgressBar::hidden(),
+        interrupt: None,
+    }
+}
+
+// An alphabet of at least two distinct alleles
+fn alphabet() -> impl Strategy<Value = Vec<u8>> {
+    prop::collection::btree_set(any::<u8>(), 2..16).prop_map(|a| a.into_iter().collect())
+}
+
+// An alphabet and a genotype drawn from it
+fn genotype(length: std::ops::Range<usize>) -> impl Strategy<Value = (Vec<u8>, Vec<u8>)> {
+    (alphabet(), length).prop_flat_map(|(alphabet, length)| {
+        let g = prop::collection::vec(prop::sample::select(alphabet.clone()), length);
+        (Just(alphabet), g)
+    })
+}
+
+// Two parents of the same length drawn from the same alphabet
+fn parents() -> impl Strategy<Value = (Vec<u8>, Vec<u8>, Vec<u8>)> {
+    genotype(1..64).prop_flat_map(|(alphabet, a)| {
+        let b = prop::collection::vec(prop::sample::select(alphabet.clone()), a.len());
+        (Just(alphabet), Just(a), b)
+    })
+}
+
+fn sex_method() -> impl Strategy<Value = SexMethod> {
+    prop_oneof![
+        Just(SexMethod::One),
+        Just(SexMethod::Two),
+        Just(SexMethod::Uniform),
+    ]
+}
+
+fn selection_method() -> impl Strategy<Value = SelectionMethod> {
+    prop_oneof![
+        Just(SelectionMethod::Equal),
+        Just(SelectionMethod::Replacement),
+        Just(SelectionMethod::Remainder),
+    ]
+}
+
+fn fitness() -> impl Strategy<Value = Fitness> {
+    prop_oneof![
+        1 => Just(Fitness::Invalid),
+        4 => (0.0..1000.0).prop_map(Fitness::Valid),
+    ]
+}
+
+proptest! {
+    // Ensure crossover children only contain alleles from their parents at each locus
+    #[test]
+    fn crossover_inherits_parent_alleles(
+        (alphabet, a, b) in parents(),
+        sex_method in sex_method(),
+    ) {
+        let generation = generation(knapsack(a.len(), alphabet), sex_method, 0.0);
+        let mut child = (
+            Genotype::from(a.clone(), Fitness::Invalid),
+            Genotype::from(b.clone(), Fitness::Invalid),
+        );
+
+        child.0.reproduce(&mut child.1, &generation);
+
+        prop_assert_eq!(child.0.len(), a.len());
+        prop_assert_eq!(child.1.len(), b.len());
+
+        for i in 0..a.len() {
+            let (c0, c1) = (child.0.genotype[i], child.1.genotype[i]);
+            prop_assert!((c0 == a[i] && c1 == b[i]) || (c0 == b[i] && c1 == a[i]));
+        }
+    }
+
+    // Ensure mutation with a rate of 0 is the identity
+    #[test]
+    fn mutation_rate_zero_is_identity(
+        (alphabet, g) in genotype(1..64),
+        force_mutation in any::<bool>(),
+    ) {
+        let problem = knapsack(g.len(), alphabet);
+        let mut genotype = Genotype::from(g.clone(), Fitness::Invalid);
+
+        problem.mutate(0.0, force_mutation, &mut genotype);
+
+        prop_assert_eq!(genotype.genotype, g);
+    }
+
+    // Ensure forced mutation changes every selected gene to another allele
+    #[test]
+    fn forced_mutation_changes_every_gene((alphabet, g) in genotype(1..64)) {
+        let problem = knapsack(g.len(), alphabet.clone());
+        let mut genotype = Genotype::from(g.clone(), Fitness::Invalid);
+
+        problem.mutate(1.0, true, &mut genotype);
+
+        for (old, new) in g.iter().zip(genotype.genotype.iter()) {
+            prop_assert_ne!(old, new);
+            prop_assert!(alphabet.contains(new));
+        }
+    }
+
+    // Ensure forced mutation doesn't fail when there is no other allele to choose
+    #[test]
+    fn forced_mutation_single_allele(allele in any::<u8>(), length in 1usize..64) {
+        let problem = knapsack(length, vec![allele]);
+        let mut genotype = Genotype::from(vec![allele; length], Fitness::Invalid);
+
+        problem.mutate(1.0, true, &mut genotype);
+
+        prop_assert_eq!(genotype.genotype, vec![allele; length]);
+    }
+
+    // Ensure selection never produces an intermediate pool larger than its capacity
+    #[test]
+    fn selection_respects_capacity(
+        fitnesses in prop::collection::vec(fitness(), 1..32),
+        capacity in 1usize..128,
+        selection_method in selection_method(),
+    ) {
+        let mut generation = generation(knapsack(1, vec![b'0', b'1']), SexMethod::One, 0.0);
+        generation.selection_method = selection_method;
+        generation.population = fitnesses
+            .into_iter()
+            .map(|f| Genotype::from(vec![b'0'], f))
+            .collect();
+        generation.intermediate = Vec::with_capacity(capacity);
+
+        generation.select_genitors();
+
+        prop_assert!(!generation.intermediate.is_empty());
+        prop_assert!(generation.intermediate.len() <= capacity);
+    }
+}
@@ -0,0 +1,226 @@
+use clap::Parser;
+use genetic::genetic::*;
+use std::sync::mpsc::channel;
+
+fn generation(args: &[&str]) -> Generation {
+    Generation::from(Args::parse_from(
+        std::iter::once("genetic").chain(args.iter().copied()),
+    ))
+}
+
+// Ensure the best genotypes are valid, distinct and sorted best first
+fn check_best(result: &RunResult, num: usize) {
+    assert!(!result.best.is_empty());
+    assert!(result.best.len() <= num);
+
+    for g in &result.best {
+        assert!(matches!(g.fitness, Fitness::Valid(_)));
+    }
+
+    for (i, a) in result.best.iter().enumerate() {
+        for b in &result.best[i + 1..] {
+            assert_ne!(a.genotype, b.genotype);
+            assert!(a.fitness >= b.fitness);
+        }
+    }
+
+    // the best genotype overall must be the best of some generation
+    let top = result
+        .history
+        .iter()
+        .max_by(|a, b| a.partial_cmp(b).unwrap())
+        .unwrap();
+    assert_eq!(&result.best[0].fitness, top);
+}
+
+#[test]
+fn run_knapsack() {
+    let mut generation = generation(&[
+        "--problem",
+        "knapsack",
+        "--file",
+        "testdata/knapsack/test1.kp",
+        "--force-create",
+        "--max-generations",
+        "20",
+        "--best",
+        "5",
+    ]);
+
+    let result = generation.run();
+
+    assert_eq!(result.stop_reason, StopReason::MaxGenerations);
+    assert_eq!(result.history.len(), 20);
+    check_best(&result, 5);
+
+    for g in &result.best {
+        assert_eq!(g.len(), 25);
+        assert_eq!(g.fitness, generation.problem.fitness(&g.genotype));
+    }
+}
+
+#[test]
+fn run_stocks() {
+    let mut generation = generation(&[
+        "--problem",
+        "stocks",
+        "--file",
+        "testdata/AAPL-1.txt",
+        "testdata/tests/one-year-sinusoidal.txt",
+        "--population",
+        "20",
+        "--intermediate-population",
+        "40",
+        "--max-generations",
+        "5",
+        "--best",
+        "3",
+    ]);
+
+    let result = generation.run();
+
+    assert_eq!(result.stop_reason, StopReason::MaxGenerations);
+    assert_eq!(result.history.len(), 5);
+    check_best(&result, 3);
+
+    for g in &result.best {
+        assert_eq!(g.len(), 14);
+        assert_eq!(g.fitness, generation.problem.fitness(&g.genotype));
+    }
+}
+
+#[test]
+fn run_interrupted() {
+    let mut generation = generation(&[
+        "--problem",
+        "knapsack",
+        "--file",
+        "testdata/knapsack/test1.kp",
+        "--force-create",
+        "--max-generations",
+        "100",
+    ]);
+
+    let (tx, rx) = channel();
+    tx.send(()).unwrap();
+    generation.interrupt = Some(rx);
+
+    let result = generation.run();
+
+    assert_eq!(result.stop_reason, StopReason::Interrupted);
+    assert_eq!(result.history.len(), 1);
+    check_best(&result, 1);
+}
+
+// Ensure rule trees evolve within their depth and keep their fitness
+#[test]
+fn run_stocks_rules() {
+    let mut generation = generation(&[
+        "--problem",
+        "stocks",
+        "--file",
+        "testdata/AAPL-1.txt",
+        "testdata/tests/one-year-sinusoidal.txt",
+        "--rules",
+        "--max-depth",
+        "3",
+        "--parsimony",
+        "100",
+        "--mutation-rate",
+        "0.2",
+        "--population",
+        "20",
+        "--intermediate-population",
+        "40",
+        "--max-generations",
+        "5",
+        "--best",
+        "3",
+    ]);
+
+    let result = generation.run();
+
+    assert_eq!(result.history.len(), 5);
+    check_best(&result, 3);
+
+    for g in &result.best {
+        let text = String::from_utf8(g.genotype.clone()).unwrap();
+        let depth = text
+            .chars()
+            .scan(0i32, |open, c| {
+                *open += (c == '(') as i32 - (c == ')') as i32;
+                Some(*open)
+            })
+            .max()
+            .unwrap();
+
+        // indicators and negated comparisons are parenthesized too, so this is loose
+        assert!(depth <= 4, "{text}");
+        assert_eq!(g.fitness, generation.problem.fitness(&g.genotype));
+    }
+}
+
+// Ensure evaluating a genotype the market can't trade says why and fails,
+// instead of panicking
+#[test]
+fn run_evaluate_invalid() {
+    let evaluate = |args: &[&str]| {
+        std::process::Command::new(env!("CARGO_BIN_EXE_genetic"))
+            .args(["--problem", "stocks", "--file", "testdata/AAPL-1.txt", "--evaluate"])
+            .args(args)
+            .output()
+            .unwrap()
+    };
+
+    for (args, error) in [
+        (
+            vec!["--genitors", "x010|m002|s101"],
+            "x010|m002|s101 is invalid: unknown indicator x in gene x010",
+        ),
+        (
+            vec!["--exits", "--genitors", "e010|m002|s101"],
+            "e010|m002|s101 is invalid: exit genes and stops are expected with --exits",
+        ),
+    ] {
+        let output = evaluate(&args);
+        let stdout = String::from_utf8(output.stdout).unwrap();
+
+        assert_eq!(output.status.code(), Some(1), "{args:?}");
+        assert!(stdout.contains(error), "{stdout}");
+        assert!(!String::from_utf8(output.stderr).unwrap().contains("panicked"));
+    }
+
+    assert!(evaluate(&["--genitors", "e010|m002|s101"]).status.success());
+}
+
+// Ensure walk-forward windows without a strategy that can be traded are skipped,
+// instead of panicking
+#[test]
+fn run_walk_forward_skipped() {
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_genetic"))
+        .args(["--problem", "stocks", "--file", "testdata/AAPL-1.txt"])
+        .args(["--walk-forward", "250", "125", "--population", "1", "--max-generations", "1"])
+        .args(["--genitors", "x010|m002|s101"])
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+
+    assert!(output.status.success(), "{stdout}");
+    assert!(stdout.contains("Window 1: skipped, no valid strategy in sample"), "{stdout}");
+    assert!(stdout.ends_with("Out of sample: $0.00\n"), "{stdout}");
+}
+
+// Ensure paper trading at the open refuses bars from standard input, which have no
+// open, before reading any
+#[test]
+fn run_paper_trade_at_open() {
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_genetic"))
+        .args(["--trade-at-open", "paper", "s002&s002|s002"])
+        .stdin(std::process::Stdio::null())
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stderr.contains("needs a price file with opens, not standard input"), "{stderr}");
+}
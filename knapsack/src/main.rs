@@ -74,26 +74,15 @@ impl fmt::Display for Knapsack {
             sum += item.value;
         }
 
-        match write!(
+        write!(
             f,
             "{} items, weight {} lbs, ${} total",
             self.num_items,
             self.weight,
-            sum)
-        {
-            Ok(()) => {},
-            Err(e) => {
-                eprint!("Failed to print: {}", e);
-            }
-        }
+            sum)?;
 
         for item in self.items.clone() {
-            match write!(f, "\n{}", item) {
-                Ok(()) => {},
-                Err(e) => {
-                    eprint!("Failed to print: {}", e);
-                }
-            }
+            write!(f, "\n{}", item)?;
         }
 
         Ok(())
@@ -153,7 +142,7 @@ fn parse_file(file_name: String) -> io::Result<Knapsack> {
 // Function to increment a bit vector
 fn increment(b: &mut BitVec::<u8>) {
     for bit in b.iter_mut().rev() {
-        if !bit.clone() {
+        if !*bit {
             bit.commit(true);
             break;
         } else {
@@ -220,7 +209,7 @@ fn exhaustive(k: &Knapsack, d: Option<Duration>) -> Knapsack {
     }
 
     Knapsack {
-        num_items: max.iter_ones().count() as usize,
+        num_items: max.iter_ones().count(),
         weight: max_weight,
         items: max.iter_ones().map(|i| k.items[i].clone()).collect()
     }
@@ -232,18 +221,15 @@ fn exhaustive_pruning(k: &Knapsack, d: Option<Duration>) -> Knapsack {
     bits.resize(k.items.len(), false);
 
     let (m, w, _, _) = recur(
-        &k,
+        k,
         (bits.clone(), 0, 0, true),
         bits,
         -1,
-        match d {
-            None => { None },
-            Some(d) => { Some((Instant::now(), d)) }
-        }
+        d.map(|d| (Instant::now(), d))
     );
 
     Knapsack {
-        num_items: m.count_ones() as usize,
+        num_items: m.count_ones(),
         weight: w,
         items: m.iter_ones().map(|i| k.items[i].clone()).collect()
     }
@@ -275,13 +261,12 @@ fn recur(
                 &String::from("better_exhaustive"), 
                 Some(time.elapsed()),
                 &Knapsack {
-                    num_items: m.count_ones() as usize,
+                    num_items: m.count_ones(),
                     weight: w,
                     items: m.iter_ones().map(|i| k.items[i].clone()).collect()
                 }
             );
             panic!("guh");
-            return (m, w, v, false);
         }
     }
     let (weight, value) = b.iter_ones()
@@ -309,21 +294,21 @@ fn recur(
 
     // We're over the weight limit, we can return and not recur,
     // thereby pruning this branch, or maybe we found a new max
-    return (m, w, v, cont);
+    (m, w, v, cont)
 }
 
 fn get_weight(bits: &BitVec<u8>, k: &Knapsack) -> usize {
     // Throwback to SML
     bits.iter_ones()
         .map(|i| k.items[i].weight)
-        .fold(0, |acc, x| acc + x)
+        .sum()
 }
 
 #[allow(dead_code)]
 fn get_value(bits: &BitVec<u8>, k: &Knapsack) -> usize {
     bits.iter_ones()
         .map(|i| k.items[i].value)
-        .fold(0, |acc, x| acc + x)
+        .sum()
 }
 
 fn get_wv(bits: &BitVec<u8>, k: &Knapsack) -> (usize, usize) {
@@ -339,7 +324,7 @@ fn swap(b: &BitVec<u8>, k: &Knapsack) -> BitVec<u8> {
 
     loop {
         let (mut o, mut z): (isize, isize) = (-1, -1);
-        let (w, v) = get_wv(&bits, &k);
+        let (w, v) = get_wv(&bits, k);
 
         for one in bits.iter_ones() {
             for zero in bits.iter_zeros() {
@@ -371,7 +356,7 @@ fn add(b: &BitVec<u8>, k: &Knapsack) -> BitVec<u8> {
     let mut bits = b.clone();
 
     loop {
-        let w = get_weight(&bits, &k);
+        let w = get_weight(&bits, k);
 
         if w == k.weight { break; }
 
@@ -397,7 +382,7 @@ fn sub(b: &BitVec<u8>, k: &Knapsack) -> BitVec<u8> {
     let mut bits = b.clone();
 
     loop {
-        let w = get_weight(&bits, &k);
+        let w = get_weight(&bits, k);
 
         if w <= k.weight { break; }
 
@@ -431,20 +416,20 @@ fn hill_climb(k: &Knapsack) -> Knapsack {
 
         if before.elapsed() >= Duration::from_secs(1200) { break; }
 
-        weight = get_weight(&bits, &k);
+        weight = get_weight(&bits, k);
 
         if weight < k.weight {
-            bits = add(&bits, &k);
+            bits = add(&bits, k);
         } else if weight > k.weight {
-            bits = sub(&bits, &k);
+            bits = sub(&bits, k);
         } else {
-            bits = swap(&bits, &k);
+            bits = swap(&bits, k);
         }
     }
 
     Knapsack {
-        num_items: bits.count_ones() as usize,
-        weight: get_weight(&bits, &k),
+        num_items: bits.count_ones(),
+        weight: get_weight(&bits, k),
         items: bits.iter_ones().map(|i| k.items[i].clone()).collect()
     }
 }
@@ -61,7 +61,7 @@ impl Player for AI {
         if let Some(moves) = game.moves() {
             let mut rng = rand::thread_rng();
 
-            let probs = map.get(game.format().as_str()).expect(format!("Game not found: {}", game.format()).as_str());
+            let probs = map.get(game.format().as_str()).unwrap_or_else(|| panic!("Game not found: {}", game.format()));
 
             let dist = WeightedIndex::new(
                 moves
@@ -45,7 +45,7 @@ struct Args {
 
 fn build_tree(game: TicTacToe, map: &mut HashMap<String, [f64; 9]>) {
     if let Some(moves) = game.moves() {
-        let mut probs = [if moves.len() > 0 { 1.0 / moves.len() as f64 } else { 0.0 }; 9];
+        let mut probs = [if !moves.is_empty() { 1.0 / moves.len() as f64 } else { 0.0 }; 9];
 
         for (j, _) in game
             .board
@@ -36,11 +36,11 @@ impl Game for TicTacToe {
     fn evaluate(&self) -> Status {
         // Check rows
         for row in 0..3 {
-            if self.board[0 + row * 3] == self.board[1 + row * 3]
+            if self.board[row * 3] == self.board[1 + row * 3]
                 && self.board[1 + row * 3] == self.board[2 + row * 3]
-                && self.board[0 + row * 3] != b'_'
+                && self.board[row * 3] != b'_'
             {
-                return Status::Win(self.board[0 + row * 3] as char);
+                return Status::Win(self.board[row * 3] as char);
             }
         }
 
@@ -76,11 +76,11 @@ impl Game for TicTacToe {
         let mut moves = Vec::new();
 
         match self.evaluate() {
-            Status::Win(_) | Status::Draw => return None,
+            Status::Win(_) | Status::Draw => None,
             _ => {
                 for i in 0..9 {
                     if self.board[i] == b'_' {
-                        let mut new_board = self.board.clone();
+                        let mut new_board = self.board;
                         new_board[i] = self.next as u8;
                         moves.push((i, TicTacToe::from(new_board)));
                     }
@@ -119,7 +119,7 @@ impl Game for TicTacToe {
                     history.push((
                         self.format(),
                         self.moves()
-                            .unwrap_or(Vec::<(usize, TicTacToe)>::new())
+                            .unwrap_or_default()
                             .len(),
                         index,
                     ));
@@ -214,13 +214,13 @@ impl Display for TicTacToe {
             s.push_str(
                 format!(
                     " {} | {} | {} \n",
-                    chars[0 + i * 3],
+                    chars[i * 3],
                     chars[1 + i * 3],
                     chars[2 + i * 3]
                 )
                 .as_str(),
             );
-            s.push_str(format!("---|---|---\n").as_str());
+            s.push_str("---|---|---\n");
         }
 
         s.push_str(